@@ -0,0 +1,49 @@
+//! Compares every memory format on size and how long it takes to encode and decode.
+//! Run with `cargo bench --bench memory_codecs`.
+
+use std::time::{Duration, Instant};
+
+use screepies::offline::{Format, Memory, MemoryCodec};
+
+const ROOMS: u32 = 5;
+const CREEPS: u32 = 150;
+const ITERATIONS: u32 = 20;
+
+fn main() {
+	let data = Memory::sample(ROOMS, CREEPS);
+	println!("Sample memory with {ROOMS} rooms and {CREEPS} Creeps, averaged over {ITERATIONS} runs");
+	println!("{:<12} {:<11} {:>10} {:>12} {:>12}", "Codec", "Compressed", "Size", "Encode", "Decode");
+
+	for codec in MemoryCodec::ALL {
+		for compressed in [false, true] {
+			let format = Format { codec, compressed };
+			let name = format!("{codec:?}");
+			let bytes = match data.encode(format) {
+				Ok(bytes) => bytes,
+				Err(err) => {
+					println!("{name:<12} {compressed:<11} failed to encode: {err}");
+					continue;
+				}
+			};
+			if let Err(err) = Memory::decode(&bytes) {
+				println!("{name:<12} {compressed:<11} failed to decode: {err}");
+				continue;
+			}
+
+			let encode = time(|| drop(data.encode(format)));
+			let decode = time(|| drop(Memory::decode(&bytes)));
+			// Raw memory holds the bytes as base64, which is a third larger.
+			let size = bytes.len().div_ceil(3) * 4;
+
+			println!("{name:<12} {compressed:<11} {size:>10} {encode:>12.2?} {decode:>12.2?}");
+		}
+	}
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+	let start = Instant::now();
+	for _ in 0..ITERATIONS {
+		f();
+	}
+	start.elapsed() / ITERATIONS
+}
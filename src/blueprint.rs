@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use screeps::{RoomXY, StructureType};
+
+/// Blueprints compiled into the bot.
+const BLUEPRINT_DATA: &[&str] = &[
+	include_str!("../data/blueprints/core.json"),
+	include_str!("../data/blueprints/labs.json"),
+];
+
+thread_local! {
+	pub static BLUEPRINTS: Vec<Blueprint> = BLUEPRINT_DATA.iter()
+		.map(|data| serde_json::from_str(data).expect("Embedded blueprint should be valid"))
+		.collect();
+}
+
+/// Gets one of the embedded blueprints by name.
+pub fn get(name: &str) -> Option<Blueprint> {
+	BLUEPRINTS.with(|blueprints| blueprints.iter().find(|b| b.name == name).cloned())
+}
+
+/// A set of structures laid out around an anchor.
+///
+/// This uses the same JSON layout as the community building planner's export, with
+/// positions given as room coordinates. The extra `anchor` field marks the tile the
+/// blueprint is placed by. Exports without it are anchored on their storage, or on
+/// the middle of their bounds if they have none.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Blueprint {
+	#[serde(default)]
+	pub name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub rcl: Option<u8>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub anchor: Option<BlueprintPos>,
+	pub buildings: BTreeMap<String, BlueprintBuildings>,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct BlueprintBuildings {
+	pub pos: Vec<BlueprintPos>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BlueprintPos {
+	pub x: i16,
+	pub y: i16,
+}
+
+/// How a blueprint is turned before being placed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transform {
+	/// Mirrored along the vertical axis, before rotating.
+	pub mirror: bool,
+	/// Number of quarter turns clockwise.
+	pub rotation: u8,
+}
+
+impl Transform {
+	pub fn new(rotation: u8, mirror: bool) -> Self {
+		Self { mirror, rotation: rotation % 4 }
+	}
+
+	/// Every distinct way a blueprint can be placed.
+	pub fn all() -> impl Iterator<Item = Transform> {
+		(0..8).map(|i| Transform::new(i % 4, i >= 4))
+	}
+
+	/// The offset after transforming, or None if it can't be represented, which only happens for -128.
+	pub fn apply(&self, (dx, dy): (i8, i8)) -> Option<(i8, i8)> {
+		let (mut dx, mut dy) = if self.mirror { (dx.checked_neg()?, dy) } else { (dx, dy) };
+		for _ in 0..self.rotation {
+			(dx, dy) = (dy.checked_neg()?, dx);
+		}
+		Some((dx, dy))
+	}
+}
+
+impl Blueprint {
+	/// Builds a blueprint from a list of structures around `anchor`.
+	pub fn from_structures(name: impl Into<String>, anchor: RoomXY, structures: &[(StructureType, RoomXY)]) -> Self {
+		let mut buildings: BTreeMap<String, BlueprintBuildings> = BTreeMap::new();
+		for &(ty, xy) in structures {
+			let Some(ty) = structure_name(ty) else { continue };
+			buildings.entry(ty.to_string()).or_default().pos.push(BlueprintPos { x: xy.x.u8() as i16, y: xy.y.u8() as i16 });
+		}
+
+		Self {
+			name: name.into(),
+			rcl: None,
+			anchor: Some(BlueprintPos { x: anchor.x.u8() as i16, y: anchor.y.u8() as i16 }),
+			buildings,
+		}
+	}
+
+	fn anchor(&self) -> BlueprintPos {
+		if let Some(anchor) = self.anchor {
+			return anchor;
+		}
+
+		if let Some(storage) = self.buildings.get("storage").and_then(|b| b.pos.first()) {
+			return *storage;
+		}
+
+		let all = self.buildings.values().flat_map(|b| b.pos.iter());
+		let (min_x, max_x) = all.clone().fold((i16::MAX, i16::MIN), |(min, max), p| (min.min(p.x), max.max(p.x)));
+		let (min_y, max_y) = all.fold((i16::MAX, i16::MIN), |(min, max), p| (min.min(p.y), max.max(p.y)));
+		// Halfway between two i16s is always an i16, but the sum might not be.
+		let middle = |min: i16, max: i16| ((min as i32 + max as i32) / 2) as i16;
+		BlueprintPos { x: middle(min_x, max_x), y: middle(min_y, max_y) }
+	}
+
+	/// Every structure in the blueprint as an offset from its anchor, after transforming.
+	/// Structure types this bot doesn't know about are skipped.
+	pub fn offsets(&self, transform: Transform) -> Vec<(StructureType, i8, i8)> {
+		let anchor = self.anchor();
+		let mut offsets = Vec::new();
+
+		for (name, buildings) in &self.buildings {
+			let Some(ty) = structure_type(name) else {
+				log::warn!("Blueprint '{}' has unknown structure type '{name}'", self.name);
+				continue;
+			};
+
+			for pos in &buildings.pos {
+				// Positions come from user JSON, so they can be anywhere an i16 can.
+				let (Ok(dx), Ok(dy)) = (i8::try_from(pos.x as i32 - anchor.x as i32), i8::try_from(pos.y as i32 - anchor.y as i32)) else {
+					continue;
+				};
+				let Some((dx, dy)) = transform.apply((dx, dy)) else {
+					continue;
+				};
+				offsets.push((ty, dx, dy));
+			}
+		}
+
+		offsets
+	}
+}
+
+/// Places every structure in the blueprint onto a room with its anchor at `anchor`.
+/// Returns the positions, skipping any that would fall outside the room.
+pub fn place(blueprint: &Blueprint, anchor: RoomXY, transform: Transform) -> Vec<(StructureType, RoomXY)> {
+	blueprint.offsets(transform).into_iter()
+		.filter_map(|(ty, dx, dy)| {
+			let x = u8::try_from(anchor.x.u8() as i16 + dx as i16).ok()?;
+			let y = u8::try_from(anchor.y.u8() as i16 + dy as i16).ok()?;
+			Some((ty, RoomXY::checked_new(x, y).ok()?))
+		})
+		.collect()
+}
+
+const STRUCTURE_NAMES: &[(StructureType, &str)] = &[
+	(StructureType::Spawn, "spawn"),
+	(StructureType::Extension, "extension"),
+	(StructureType::Road, "road"),
+	(StructureType::Wall, "constructedWall"),
+	(StructureType::Rampart, "rampart"),
+	(StructureType::Link, "link"),
+	(StructureType::Storage, "storage"),
+	(StructureType::Tower, "tower"),
+	(StructureType::Observer, "observer"),
+	(StructureType::PowerSpawn, "powerSpawn"),
+	(StructureType::Extractor, "extractor"),
+	(StructureType::Lab, "lab"),
+	(StructureType::Terminal, "terminal"),
+	(StructureType::Container, "container"),
+	(StructureType::Nuker, "nuker"),
+	(StructureType::Factory, "factory"),
+];
+
+/// The name the game and the building planner use for a structure type.
+pub fn structure_name(ty: StructureType) -> Option<&'static str> {
+	STRUCTURE_NAMES.iter().find(|(t, _)| *t == ty).map(|(_, name)| *name)
+}
+
+pub fn structure_type(name: &str) -> Option<StructureType> {
+	STRUCTURE_NAMES.iter().find(|(_, n)| *n == name).map(|(ty, _)| *ty)
+}
@@ -72,14 +72,248 @@ pub fn cmd_get_state(creep: JsString) -> Result<String, JsString> {
 		return Err(format!("Creep '{creep}' not found").into());
 	};
 
-	let state = crate::memory::get_memory()
-		.creep_data.remove(&creep.try_id().ok_or(JsString::from_str("Creep has no ID").unwrap())?)
-		.ok_or(JsString::from_str("No entry for Creep").unwrap())?
-		.current_task
-		.ok_or(JsString::from_str("Creep has no current task").unwrap())?
-		.1
-		.state
-		;
-
-	Ok(format!("{:?}", state))
+	crate::memory::read_memory(|memory| {
+		let (_, task) = memory.creep_data.get(&creep.name())
+			.ok_or(JsString::from_str("No entry for Creep").unwrap())?
+			.current_task.as_ref()
+			.ok_or(JsString::from_str("Creep has no current task").unwrap())?;
+		Ok(format!("{:?}", task.state))
+	})
+}
+
+#[wasm_bindgen]
+pub fn cmd_path_cache_stats() -> String {
+	let stats = crate::path_cache::stats();
+	format!(
+		"{} cached paths, {} hits, {} misses ({:.1}% hit rate), {} expired",
+		crate::path_cache::len(),
+		stats.hits,
+		stats.misses,
+		stats.hit_rate() * 100.0,
+		stats.expired,
+	)
+}
+
+/// Places construction sites for a blueprint with its anchor at `x`, `y`.  
+/// `blueprint` is either the name of an embedded blueprint, or a building planner JSON export.
+#[wasm_bindgen]
+pub fn cmd_place_blueprint(room: String, blueprint: String, x: u8, y: u8, rotation: Option<u8>, mirror: Option<bool>) -> Result<String, String> {
+	use crate::blueprint::{self, Blueprint, Transform};
+
+	let room_name = screeps::RoomName::new(&room).map_err(|_| "Invalid room name".to_string())?;
+	let Some(room) = game::rooms().get(room_name) else {
+		return Err(format!("No vision of room '{room}'"));
+	};
+
+	let blueprint: Blueprint = if blueprint.trim_start().starts_with('{') {
+		serde_json::from_str(&blueprint).map_err(|e| format!("Invalid blueprint: {e}"))?
+	} else {
+		blueprint::get(&blueprint).ok_or_else(|| format!("No blueprint named '{blueprint}'"))?
+	};
+
+	let anchor = screeps::RoomXY::checked_new(x, y).map_err(|_| "Anchor is outside the room".to_string())?;
+	let transform = Transform::new(rotation.unwrap_or(0), mirror.unwrap_or(false));
+
+	let (mut placed, mut failed) = (0, 0);
+	for (ty, xy) in blueprint::place(&blueprint, anchor, transform) {
+		match room.create_construction_site(xy.x.u8(), xy.y.u8(), ty, None) {
+			Ok(()) => placed += 1,
+			Err(_) => failed += 1,
+		}
+	}
+
+	Ok(format!("Placed {placed} construction sites, {failed} couldn't be placed"))
+}
+
+/// Exports a room's planned layout as building planner JSON.
+#[wasm_bindgen]
+pub fn cmd_export_layout(room: String) -> Result<String, String> {
+	let room_name = screeps::RoomName::new(&room).map_err(|_| "Invalid room name".to_string())?;
+
+	if !crate::layouts::loaded() {
+		return Err("Layouts haven't loaded from their segments yet, try again next tick".to_string());
+	}
+	let Some(layout) = crate::layouts::get(room_name) else {
+		return Err(format!("Room '{room}' has no planned layout"));
+	};
+
+	let blueprint = crate::blueprint::Blueprint::from_structures(room, layout.anchor, &layout.structures);
+	serde_json::to_string(&blueprint).map_err(|e| e.to_string())
+}
+
+/// Turns one of a room's job providers on or off.
+#[wasm_bindgen]
+pub fn cmd_set_job_provider(room: String, provider: String, enabled: bool) -> Result<String, String> {
+	let room_name = screeps::RoomName::new(&room).map_err(|_| "Invalid room name".to_string())?;
+	if !crate::jobs::PROVIDERS.iter().any(|p| p.name() == provider) {
+		let names: Vec<_> = crate::jobs::PROVIDERS.iter().map(|p| p.name()).collect();
+		return Err(format!("No provider named '{provider}', expected one of {names:?}"));
+	}
+
+	crate::memory::with_memory_mut(|memory| {
+		let Some(data) = memory.rooms.get_mut(&room_name) else {
+			return Err(format!("Room '{room}' has no data"));
+		};
+
+		data.disabled_providers.retain(|p| *p != provider);
+		if !enabled {
+			data.disabled_providers.push(provider.clone());
+		}
+		Ok(())
+	})?;
+
+	Ok(format!("Provider '{provider}' {} in room {room}", if enabled { "enabled" } else { "disabled" }))
+}
+
+#[wasm_bindgen]
+pub fn cmd_job_provider_stats() -> String {
+	crate::jobs::provider_stats().iter()
+		.map(|(name, stats)| format!("{name}: {:.3} CPU average over {} runs", stats.average_cpu, stats.runs))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+/// Shows where a room's energy comes from and where it goes, in energy per tick.
+#[wasm_bindgen]
+pub fn cmd_economy(room: String) -> Result<String, String> {
+	let room_name = screeps::RoomName::new(&room).map_err(|_| "Invalid room name".to_string())?;
+	let Some(room) = game::rooms().get(room_name) else {
+		return Err(format!("No vision of room '{room}'"));
+	};
+
+	let economy = crate::memory::read_memory(|memory| crate::economy::estimate(&room, &memory.creep_data));
+	Ok(format!(
+		"Income {:.2}, throughput {:.2} of {:.2} targeted\n\
+		Spawn upkeep {:.2}, build demand {:.2}, repair demand {:.2}, surplus {:.2}\n\
+		Upgrading {:.2} of {:.2} possible, building capacity {:.2}, repair capacity {:.2}",
+		economy.income,
+		economy.throughput,
+		economy.target_throughput(),
+		economy.spawn_upkeep,
+		economy.build_demand,
+		economy.repair_demand,
+		economy.surplus(),
+		economy.upgrade_spending(),
+		economy.upgrade_capacity,
+		economy.build_capacity,
+		economy.repair_capacity,
+	))
+}
+
+/// Shows how Creeps whose data has been cleared out died, and what jobs they did.
+#[wasm_bindgen]
+pub fn cmd_graveyard() -> String {
+	crate::memory::read_memory(|memory| {
+		let graveyard = &memory.graveyard;
+		let deaths: Vec<_> = graveyard.deaths.iter().map(|(cause, count)| format!("{cause:?} {count}")).collect();
+		let mut kinds = Vec::new();
+		for job in graveyard.jobs_finished.keys().chain(graveyard.jobs_failed.keys()) {
+			if !kinds.contains(&job) {
+				kinds.push(job);
+			}
+		}
+		let jobs: Vec<_> = kinds.into_iter()
+			.map(|job| {
+				let finished = graveyard.jobs_finished.get(job).copied().unwrap_or(0);
+				let failed = graveyard.jobs_failed.get(job).copied().unwrap_or(0);
+				format!("{job:?} {finished} finished, {failed} failed")
+			})
+			.collect();
+		format!("Deaths: {}\nJobs: {}", deaths.join(", "), jobs.join(", "))
+	})
+}
+
+#[derive(tabled::Tabled)]
+struct SizeRow {
+	#[tabled(rename = "Section")]
+	name: String,
+	#[tabled(rename = "Entries")]
+	entries: usize,
+	#[tabled(rename = "Bytes")]
+	bytes: usize,
+	#[tabled(rename = "Of limit")]
+	share: String,
+}
+
+/// Shows how large each part of memory is once encoded, with Creep data split up by state.
+#[wasm_bindgen]
+pub fn cmd_memory_usage() -> Result<String, String> {
+	let sizes = crate::memory::read_memory(|memory| crate::memory::sizes(memory))?;
+	Ok(size_table(sizes))
+}
+
+pub(crate) fn size_table(sizes: Vec<crate::memory::SectionSize>) -> String {
+	let rows = sizes.into_iter().map(|size| SizeRow {
+		share: format!("{:.1}%", size.bytes as f64 / crate::memory::MAX_MEMORY_SIZE as f64 * 100.0),
+		name: size.name,
+		entries: size.entries,
+		bytes: size.bytes,
+	});
+	tabled::Table::new(rows).to_string()
+}
+
+/// Shows memory as JSON, or just the part of it at `path`.
+/// Paths are JSON pointers, such as `/creep_data/Alice` or `/rooms/W1N1/planned_level`.
+#[wasm_bindgen]
+pub fn cmd_memory_json(path: Option<String>) -> Result<String, String> {
+	let path = path.unwrap_or_default();
+	let memory = crate::memory::read_memory(crate::memory::to_json)?;
+	let value = memory.pointer(&path).ok_or_else(|| format!("Nothing at '{path}'"))?;
+	serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+/// Replaces the part of memory at `path` with `json`, adding it if it isn't there yet.
+#[wasm_bindgen]
+pub fn cmd_memory_replace(path: String, json: String) -> Result<String, String> {
+	let value: serde_json::Value = serde_json::from_str(&json).map_err(|e| format!("Invalid JSON: {e}"))?;
+	edit_memory(&path, |slot| *slot = value)
+}
+
+/// Merges `patch` into the part of memory at `path`.
+/// Objects are merged key by key, `null` removes a key, and anything else replaces what was there.
+#[wasm_bindgen]
+pub fn cmd_memory_patch(path: String, patch: String) -> Result<String, String> {
+	let patch: serde_json::Value = serde_json::from_str(&patch).map_err(|e| format!("Invalid JSON: {e}"))?;
+	edit_memory(&path, |slot| merge_json(slot, patch))
+}
+
+/// Edits memory as JSON. Nothing is changed unless the result still reads as memory.
+fn edit_memory(path: &str, edit: impl FnOnce(&mut serde_json::Value)) -> Result<String, String> {
+	crate::memory::with_memory_mut(|memory| {
+		let mut value = crate::memory::to_json(memory)?;
+		edit(json_slot(&mut value, path)?);
+		*memory = crate::memory::from_json(value).map_err(|e| format!("Edited memory isn't valid: {e}"))?;
+		Ok(format!("Updated '{path}'"))
+	})
+}
+
+/// The value at `path`. If there isn't one, it's added to its parent as null.
+fn json_slot<'a>(root: &'a mut serde_json::Value, path: &str) -> Result<&'a mut serde_json::Value, String> {
+	if root.pointer(path).is_none() {
+		let (parent, key) = path.rsplit_once('/').ok_or_else(|| format!("Invalid path '{path}'"))?;
+		let Some(serde_json::Value::Object(parent)) = root.pointer_mut(parent) else {
+			return Err(format!("Nothing at '{parent}' to add '{key}' to"));
+		};
+		parent.insert(key.replace("~1", "/").replace("~0", "~"), serde_json::Value::Null);
+	}
+	root.pointer_mut(path).ok_or_else(|| format!("Nothing at '{path}'"))
+}
+
+fn merge_json(target: &mut serde_json::Value, patch: serde_json::Value) {
+	let serde_json::Value::Object(patch) = patch else {
+		*target = patch;
+		return;
+	};
+	if !target.is_object() {
+		*target = serde_json::Value::Object(Default::default());
+	}
+	let serde_json::Value::Object(target) = target else { unreachable!() };
+
+	for (key, value) in patch {
+		if value.is_null() {
+			target.remove(&key);
+		} else {
+			merge_json(target.entry(key).or_insert(serde_json::Value::Null), value);
+		}
+	}
 }
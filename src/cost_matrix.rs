@@ -0,0 +1,319 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, hash::{DefaultHasher, Hash, Hasher}};
+
+use screeps::{find, ConstructionSite, LocalCostMatrix, RoomName, RoomXY, StructureObject, StructureType, Terrain};
+
+use crate::{segments, utils::prelude::*};
+
+const ROAD_COST: u8 = 1;
+const BLOCKED_COST: u8 = u8::MAX;
+/// Cost of tiles a hostile tower can reach at full strength.
+const TOWER_DANGER_COST: u8 = 40;
+/// Cost of tiles a hostile tower can reach at reduced strength.
+const TOWER_FALLOFF_COST: u8 = 15;
+/// How often structures are hashed even if their count hasn't changed, in ticks.
+/// Catches changes that keep the count, such as a rampart going public or a road replaced by a rampart.
+const FULL_CHECK_RATE: u32 = 50;
+
+thread_local! {
+	static MATRICES: RefCell<HashMap<RoomName, CachedMatrix>> = RefCell::new(HashMap::new());
+	/// Whether the cache has changed since it was last written to its segment.
+	static DIRTY: Cell<bool> = const { Cell::new(false) };
+	static LOADED: Cell<bool> = const { Cell::new(false) };
+	/// Bumped every time a room's matrix is rebuilt or dropped, so paths through it can be expired.
+	static GENERATIONS: RefCell<HashMap<RoomName, u32>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct CachedMatrix {
+	/// Only tiles with a cost other than the terrain's are stored.
+	costs: Vec<(RoomXY, u8)>,
+	/// Hash of every structure and site in the room, used to notice when they change.
+	signature: u64,
+	/// How many structures and sites the room had, checked every tick before hashing them.
+	#[serde(default)]
+	counts: (usize, usize),
+	/// The tick the signature was last checked on.
+	#[serde(skip)]
+	checked: u32,
+	#[serde(skip)]
+	matrix: Option<LocalCostMatrix>,
+}
+
+impl CachedMatrix {
+	fn matrix(&mut self) -> &LocalCostMatrix {
+		self.matrix.get_or_insert_with(|| {
+			let mut matrix = LocalCostMatrix::new();
+			for &(xy, cost) in &self.costs {
+				matrix.set(xy, cost);
+			}
+			matrix
+		})
+	}
+}
+
+/// Temporary costs layered on top of a room's cached matrix for a single search.
+#[derive(Clone, Debug)]
+pub enum Overlay {
+	/// Treat every Creep in the room as an obstacle.
+	Creeps,
+	/// Block every tile within `radius` of `pos`.
+	Avoid { pos: Position, radius: u8 },
+	/// Set a single tile to a specific cost.
+	Tile { pos: Position, cost: u8 },
+}
+
+/// Call once per tick, before any pathing happens.
+/// Loads the cache from its segments after a reset, and writes it back when it has changed.
+pub fn tick() {
+	if !LOADED.get() {
+		load_segment();
+	}
+
+	if DIRTY.get() {
+		save_segment();
+	}
+}
+
+/// Rebuilds the room's matrix if its structures changed since it was cached.
+/// Structures are only hashed when their count changes, or every `FULL_CHECK_RATE` ticks.
+pub fn refresh(room: &Room) {
+	let now = game::time();
+	let checked = MATRICES.with_borrow(|matrices| {
+		matrices.get(&room.name()).is_some_and(|cached| cached.checked == now)
+	});
+	if checked {
+		return;
+	}
+
+	let structures = room.find(find::STRUCTURES, None);
+	let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+	let counts = (structures.len(), sites.len());
+
+	let stale = MATRICES.with_borrow_mut(|matrices| {
+		let Some(cached) = matrices.get_mut(&room.name()) else {
+			return true;
+		};
+		cached.checked = now;
+		if cached.counts != counts {
+			return true;
+		}
+		now % FULL_CHECK_RATE == 0 && cached.signature != signature(&structures, &sites)
+	});
+
+	if stale {
+		log::debug!("Rebuilding cost matrix for room {}", room.name());
+		let cached = build(room, &structures, &sites);
+		MATRICES.with_borrow_mut(|matrices| matrices.insert(room.name(), cached));
+		bump_generation(room.name());
+		DIRTY.set(true);
+	}
+}
+
+/// Drops the cached matrix for a room so it's rebuilt the next time it's needed.
+pub fn invalidate(room_name: RoomName) {
+	MATRICES.with_borrow_mut(|matrices| matrices.remove(&room_name));
+	bump_generation(room_name);
+	DIRTY.set(true);
+}
+
+/// The current generation of a room's matrix.  
+/// Anything derived from the matrix is stale once this changes.
+pub fn generation(room_name: RoomName) -> u32 {
+	GENERATIONS.with_borrow(|generations| generations.get(&room_name).copied().unwrap_or(0))
+}
+
+fn bump_generation(room_name: RoomName) {
+	GENERATIONS.with_borrow_mut(|generations| {
+		let generation = generations.entry(room_name).or_insert(0);
+		*generation = generation.wrapping_add(1);
+	});
+}
+
+/// Gets the matrix for a room with the given overlays applied.
+/// Returns None if the room has never been seen, in which case only terrain is known.
+pub fn get(room_name: RoomName, overlays: &[Overlay]) -> Option<LocalCostMatrix> {
+	if let Some(room) = game::rooms().get(room_name) {
+		refresh(&room);
+	}
+
+	let mut matrix = MATRICES.with_borrow_mut(|matrices| {
+		matrices.get_mut(&room_name).map(|cached| cached.matrix().clone())
+	})?;
+
+	for overlay in overlays {
+		apply_overlay(&mut matrix, room_name, overlay);
+	}
+
+	Some(matrix)
+}
+
+fn apply_overlay(matrix: &mut LocalCostMatrix, room_name: RoomName, overlay: &Overlay) {
+	match overlay {
+		Overlay::Creeps => {
+			let Some(room) = game::rooms().get(room_name) else {
+				return;
+			};
+			for creep in room.find(find::CREEPS, None) {
+				matrix.set(creep.pos().xy(), BLOCKED_COST);
+			}
+			for creep in room.find(find::POWER_CREEPS, None) {
+				matrix.set(creep.pos().xy(), BLOCKED_COST);
+			}
+		}
+		Overlay::Avoid { pos, radius } => {
+			if pos.room_name() != room_name {
+				return;
+			}
+			let radius = *radius as i32;
+			for dx in -radius..=radius {
+				for dy in -radius..=radius {
+					if let Ok(pos) = pos.checked_add((dx, dy)) && pos.room_name() == room_name {
+						matrix.set(pos.xy(), BLOCKED_COST);
+					}
+				}
+			}
+		}
+		Overlay::Tile { pos, cost } => {
+			if pos.room_name() == room_name {
+				matrix.set(pos.xy(), *cost);
+			}
+		}
+	}
+}
+
+/// Hashes each structure by type, position and who can walk through it, and each site by type and position.
+/// The hashes are added up so the order `find` returns them in doesn't matter.
+fn signature(structures: &[StructureObject], sites: &[ConstructionSite]) -> u64 {
+	let structures = structures.iter().map(|structure| {
+		let mut hasher = DefaultHasher::new();
+		structure.structure_type().hash(&mut hasher);
+		structure.pos().xy().hash(&mut hasher);
+		structure.as_owned().map(|owned| owned.my()).hash(&mut hasher);
+		if let StructureObject::StructureRampart(rampart) = structure {
+			rampart.is_public().hash(&mut hasher);
+		}
+		hasher.finish()
+	});
+	let sites = sites.iter().map(|site| {
+		let mut hasher = DefaultHasher::new();
+		site.structure_type().hash(&mut hasher);
+		site.pos().xy().hash(&mut hasher);
+		hasher.finish()
+	});
+
+	structures.chain(sites).fold(0, u64::wrapping_add)
+}
+
+fn build(room: &Room, structures: &[StructureObject], sites: &[ConstructionSite]) -> CachedMatrix {
+	let mut matrix = LocalCostMatrix::new();
+	let terrain = room.get_terrain();
+
+	// Hostile towers go first so roads and walls are layered on top.
+	// Natural walls are skipped, since any cost set on them would make them walkable.
+	for structure in structures {
+		let StructureObject::StructureTower(tower) = structure else { continue };
+		if tower.my() {
+			continue;
+		}
+
+		let center = structure.pos();
+		for dx in -20i32..=20 {
+			for dy in -20i32..=20 {
+				let Ok(pos) = center.checked_add((dx, dy)) else { continue };
+				if pos.room_name() != room.name() || terrain.get_xy(pos.xy()) == Terrain::Wall {
+					continue;
+				}
+				let cost = match dx.abs().max(dy.abs()) {
+					r if r <= screeps::TOWER_OPTIMAL_RANGE as i32 => TOWER_DANGER_COST,
+					_ => TOWER_FALLOFF_COST,
+				};
+				matrix.set(pos.xy(), matrix.get(pos.xy()).max(cost));
+			}
+		}
+	}
+
+	for structure in structures {
+		let xy = structure.pos().xy();
+		let cost = match structure.structure_type() {
+			StructureType::Road => {
+				// Roads shouldn't make an otherwise blocked tile walkable.
+				if matrix.get(xy) == BLOCKED_COST {
+					continue;
+				}
+				// A road in tower range is cheaper to walk, but still dangerous.
+				ROAD_COST.max(matrix.get(xy))
+			}
+			StructureType::Container => continue,
+			StructureType::Rampart => match structure {
+				StructureObject::StructureRampart(rampart) if rampart.my() || rampart.is_public() => continue,
+				_ => BLOCKED_COST,
+			},
+			_ => BLOCKED_COST,
+		};
+		matrix.set(xy, cost);
+	}
+
+	// Our own sites will become structures, so don't walk over the ones that will block.
+	for site in sites {
+		if !matches!(site.structure_type(), StructureType::Road | StructureType::Container | StructureType::Rampart) {
+			matrix.set(site.pos().xy(), BLOCKED_COST);
+		}
+	}
+
+	let costs = (0..50u8)
+		.flat_map(|x| (0..50u8).map(move |y| (x, y)))
+		.filter_map(|(x, y)| {
+			let xy = RoomXY::checked_new(x, y).ok()?;
+			let cost = matrix.get(xy);
+			(cost != 0).then_some((xy, cost))
+		})
+		.collect();
+
+	CachedMatrix {
+		costs,
+		signature: signature(structures, sites),
+		counts: (structures.len(), sites.len()),
+		checked: game::time(),
+		matrix: Some(matrix),
+	}
+}
+
+fn load_segment() {
+	let Some(decoded) = segments::read_decoded::<HashMap<RoomName, CachedMatrix>>(segments::COST_MATRICES) else {
+		// The segments haven't loaded yet, try again next tick.
+		return;
+	};
+
+	LOADED.set(true);
+
+	match decoded {
+		Ok(decoded) => {
+			log::debug!("Loaded {} cost matrices from their segments", decoded.len());
+			// Anything built since the reset is more recent than what was saved.
+			MATRICES.with_borrow_mut(|matrices| {
+				for (room, cached) in decoded {
+					matrices.entry(room).or_insert(cached);
+				}
+			});
+		}
+		Err(err) => log::warn!("Failed to load cost matrices from their segments: '{err}'"),
+	}
+}
+
+/// Rooms checked most recently are saved first, so if they don't all fit the ones left out
+/// are those without vision, which are rebuilt once they're seen again anyway.
+fn save_segment() {
+	let result = MATRICES.with_borrow(|matrices| {
+		let mut entries: Vec<_> = matrices.iter().collect();
+		entries.sort_by_key(|(_, cached)| std::cmp::Reverse(cached.checked));
+		segments::write_map(segments::COST_MATRICES, &entries)
+	});
+
+	// Nothing is retried until the cache changes again, so errors aren't repeated every tick.
+	DIRTY.set(false);
+	match result {
+		Ok(0) => {}
+		Ok(left_out) => log::warn!("{left_out} cost matrices didn't fit in their segments and will be rebuilt after a reset"),
+		Err(err) => log::error!("Failed to save cost matrices: '{err}'"),
+	}
+}
@@ -1,4 +1,4 @@
-use crate::state::{*, general_states::*, harvester::*, upgrader::*, builder::*, seppuku::*};
+use crate::state::{*, general_states::*, harvester::*, upgrader::*, builder::*, hauler::*, miner::*, seppuku::*};
 use std::{any::Any, fmt::Debug};
 use serde::ser::SerializeMap;
 
@@ -64,6 +64,8 @@ pub enum StateFlag {
 	HarvesterJob,
 	UpgraderJob,
 	BuilderJob,
+	HaulerJob,
+	MinerJob,
 }
 
 impl<'de> serde::Deserialize<'de> for DynState {
@@ -98,6 +100,8 @@ impl<'de> serde::Deserialize<'de> for DynState {
 					StateFlag::HarvesterJob => Box::new(map.next_value::<StateHarvesterJob>()?),
 					StateFlag::UpgraderJob => Box::new(map.next_value::<StateUpgraderJob>()?),
 					StateFlag::BuilderJob => Box::new(map.next_value::<StateBuilderJob>()?),
+					StateFlag::HaulerJob => Box::new(map.next_value::<StateHaulerJob>()?),
+					StateFlag::MinerJob => Box::new(map.next_value::<StateMinerJob>()?),
 				};
 
 				Ok(DynState { state, flag })
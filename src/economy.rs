@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use screeps::{
+	constants::{BUILD_POWER, CARRY_CAPACITY, CREEP_LIFE_TIME, ENERGY_REGEN_TIME, HARVEST_POWER, REPAIR_POWER, UPGRADE_CONTROLLER_POWER},
+	find, Part, StructureType,
+};
+
+use crate::{memory::CreepData, utils::prelude::*, JobFlag};
+
+/// Share of their life Creeps actually spend harvesting, since they also walk
+/// and spend what they harvested.
+const HARVEST_DUTY: f64 = 0.5;
+/// Bodies harvesting at least this much are static miners.
+const MINER_HARVEST: f64 = 10.0;
+/// The most of the room's income that may go to replacing Creeps.
+const MAX_UPKEEP_SHARE: f64 = 0.6;
+/// Pending construction and repairs are spread over this many ticks when working out demand.
+const WORK_HORIZON: f64 = 1500.0;
+/// Structures above this share of their hits don't count as needing repair.
+const REPAIR_THRESHOLD: f64 = 0.75;
+
+/// How much a body can do per tick at each kind of work.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WorkRates {
+	/// Energy harvested per tick.
+	pub harvest: f64,
+	/// Energy put into the controller per tick.
+	pub upgrade: f64,
+	/// Energy put into construction sites per tick.
+	pub build: f64,
+	/// Energy spent on repairs per tick.
+	pub repair: f64,
+	pub carry: u32,
+	/// Energy needed to spawn the body.
+	pub cost: u32,
+}
+
+impl WorkRates {
+	pub fn from_body(parts: impl IntoIterator<Item = Part>) -> Self {
+		let mut rates = Self::default();
+		for part in parts {
+			rates.cost += part.cost();
+			match part {
+				Part::Work => {
+					rates.harvest += HARVEST_POWER as f64;
+					rates.upgrade += UPGRADE_CONTROLLER_POWER as f64;
+					rates.build += BUILD_POWER as f64;
+					// Repairing costs one energy per REPAIR_POWER hits.
+					rates.repair += 1.0;
+				}
+				Part::Carry => rates.carry += CARRY_CAPACITY,
+				_ => {}
+			}
+		}
+		rates
+	}
+
+	/// Only counts parts that haven't been destroyed.
+	pub fn of_creep(creep: &Creep) -> Self {
+		Self::from_body(creep.body().iter().filter(|p| p.hits() > 0).map(|p| p.part()))
+	}
+
+	/// Energy spent per tick to keep a Creep with this body alive.
+	pub fn upkeep(&self) -> f64 {
+		self.cost as f64 / CREEP_LIFE_TIME as f64
+	}
+
+	/// Energy per tick a Creep with this body moves from sources to where it's needed.
+	pub fn throughput(&self) -> f64 {
+		if self.carry == 0 {
+			return 0.0;
+		}
+		// Miners never leave their source, and haulers carry the energy away for them.
+		if self.harvest >= MINER_HARVEST && self.carry <= CARRY_CAPACITY {
+			return self.harvest;
+		}
+		self.harvest * HARVEST_DUTY
+	}
+}
+
+/// Energy flowing through a room, in energy per tick.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RoomEconomy {
+	/// What the room's sources regenerate.
+	pub income: f64,
+	/// What the room's Creeps currently harvest and deliver.
+	/// Only Creeps on harvesting or mining jobs count, the rest spend energy rather than move it.
+	pub throughput: f64,
+	/// Replacing the room's Creeps as they die.
+	pub spawn_upkeep: f64,
+	/// What the room's Creeps could put into the controller if they did nothing else.
+	pub upgrade_capacity: f64,
+	/// What the room's Creeps could put into construction sites if they did nothing else.
+	pub build_capacity: f64,
+	/// What the room's Creeps could spend on repairs if they did nothing else.
+	pub repair_capacity: f64,
+	/// Outstanding construction, spread over the work horizon.
+	pub build_demand: f64,
+	/// Outstanding repairs, spread over the work horizon.
+	pub repair_demand: f64,
+}
+
+impl RoomEconomy {
+	/// What the room would like to be harvesting. Anything the sources make beyond what's
+	/// needed for building and repairs is still worth harvesting for the controller.
+	pub fn target_throughput(&self) -> f64 {
+		self.income
+	}
+
+	/// Energy left over once Creeps are replaced, building is done and repairs are made.
+	pub fn surplus(&self) -> f64 {
+		self.throughput - self.spawn_upkeep - self.build_demand - self.repair_demand
+	}
+
+	/// What's left for the controller, as much as the Creeps can put in.
+	pub fn upgrade_spending(&self) -> f64 {
+		self.surplus().clamp(0.0, self.upgrade_capacity)
+	}
+
+	/// Whether a Creep with `body` would add throughput the room can afford to keep up.
+	pub fn wants_creep(&self, body: &WorkRates) -> bool {
+		self.throughput < self.target_throughput()
+			&& body.throughput() > 0.0
+			&& self.affords(body)
+	}
+
+	/// Whether the room can keep up replacing a Creep with `body` on top of the ones it has.
+	pub fn affords(&self, body: &WorkRates) -> bool {
+		self.spawn_upkeep + body.upkeep() <= self.income * MAX_UPKEEP_SHARE
+	}
+}
+
+/// Estimates the room's income and what its Creeps and structures are doing with it.
+/// `creep_data` tells which jobs the Creeps are on.
+pub fn estimate(room: &Room, creep_data: &HashMap<String, CreepData>) -> RoomEconomy {
+	let mut economy = RoomEconomy {
+		income: room.find(find::SOURCES, None).iter()
+			.map(|s| s.energy_capacity() as f64 / ENERGY_REGEN_TIME as f64)
+			.sum(),
+		..Default::default()
+	};
+
+	for creep in room.find(find::MY_CREEPS, None) {
+		let rates = WorkRates::of_creep(&creep);
+		let job = creep_data.get(&creep.name()).and_then(|data| data.current_task.as_ref()).map(|(job, _)| job.job);
+		if matches!(job, Some(JobFlag::Harvest | JobFlag::Mine)) {
+			economy.throughput += rates.throughput();
+		}
+		economy.spawn_upkeep += rates.upkeep();
+		economy.upgrade_capacity += rates.upgrade;
+		economy.build_capacity += rates.build;
+		economy.repair_capacity += rates.repair;
+	}
+	// No more can be harvested than the sources make.
+	economy.throughput = economy.throughput.min(economy.income);
+
+	let remaining: u32 = room.find(find::MY_CONSTRUCTION_SITES, None).iter()
+		.map(|s| s.progress_total() - s.progress())
+		.sum();
+	economy.build_demand = remaining as f64 / WORK_HORIZON;
+
+	let damage: u32 = room.find(find::STRUCTURES, None).iter()
+		.filter(|s| !matches!(s.structure_type(), StructureType::Wall | StructureType::Rampart))
+		.filter_map(|s| {
+			let attackable = s.as_attackable()?;
+			let (hits, max) = (attackable.hits(), attackable.hits_max());
+			((hits as f64) < max as f64 * REPAIR_THRESHOLD).then_some(max - hits)
+		})
+		.sum();
+	economy.repair_demand = damage as f64 / REPAIR_POWER as f64 / WORK_HORIZON;
+
+	economy
+}
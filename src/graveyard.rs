@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+
+use vecmap::VecMap;
+
+use crate::{memory::{CreepData, MemData}, utils::prelude::*, JobFlag};
+
+/// How often data for dead Creeps is cleared out, in ticks.
+const COLLECT_RATE: u32 = 100;
+
+/// Why a Creep stopped showing up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DeathCause {
+	/// Lived out its whole life.
+	OldAge,
+	/// Was sent to be recycled before it got old.
+	Seppuku,
+	/// Died early without being told to.
+	Killed,
+	/// Wasn't tracked long enough to tell, such as Creeps from before deaths were recorded.
+	Unknown,
+}
+
+/// What every Creep that's died did over its life, added up.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Graveyard {
+	pub deaths: VecMap<DeathCause, u32>,
+	pub jobs_finished: VecMap<JobFlag, u32>,
+	pub jobs_failed: VecMap<JobFlag, u32>,
+}
+
+impl Graveyard {
+	fn bury(&mut self, data: &CreepData) {
+		*self.deaths.entry(cause(data)).or_insert(0) += 1;
+		for (job, count) in &data.jobs_finished {
+			*self.jobs_finished.entry(*job).or_insert(0) += count;
+		}
+		for (job, count) in &data.jobs_failed {
+			*self.jobs_failed.entry(*job).or_insert(0) += count;
+		}
+	}
+}
+
+fn cause(data: &CreepData) -> DeathCause {
+	match data.expires {
+		None => DeathCause::Unknown,
+		// Creeps are last seen on the tick before they expire.
+		Some(expires) if data.last_seen + 1 >= expires => DeathCause::OldAge,
+		Some(_) if data.seppuku => DeathCause::Seppuku,
+		Some(_) => DeathCause::Killed,
+	}
+}
+
+/// Call every tick for each living Creep, so it's known how it died once it's gone.
+/// Memory is only marked dirty when it matters, since `last_seen` changes every tick.
+pub fn observe(creep: &Creep, data: &mut CreepData) {
+	let now = game::time();
+	data.last_seen = now;
+	if data.expires.is_none() && let Some(to_live) = creep.ticks_to_live() {
+		data.expires = Some(now + to_live);
+		crate::memory::mark_dirty();
+	}
+	// Whether the Creep was seen on its last tick decides how it died, so that has to survive a global reset.
+	if data.expires.is_some_and(|expires| now + 1 >= expires) {
+		crate::memory::mark_dirty();
+	}
+}
+
+/// Removes data for Creeps that no longer exist, adding what they did to the graveyard first.
+pub fn collect(memory: &mut MemData) {
+	if game::time() % COLLECT_RATE != 0 {
+		return;
+	}
+
+	let alive: HashSet<_> = game::creeps().keys().collect();
+	let before = memory.creep_data.len();
+	memory.creep_data.retain(|name, data| {
+		if alive.contains(name) {
+			return true;
+		}
+		memory.graveyard.bury(data);
+		false
+	});
+
+	let dead = before - memory.creep_data.len();
+	if dead > 0 {
+		log::info!("Cleared data for {dead} dead Creeps");
+		crate::memory::mark_dirty();
+	}
+}
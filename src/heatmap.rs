@@ -0,0 +1,94 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashMap}};
+
+use screeps::{Position, RoomName, RoomXY};
+use vecmap::VecMap;
+
+use crate::utils::prelude::*;
+
+/// How often heat decays, in ticks.
+const DECAY_RATE: u32 = 100;
+/// Heat is multiplied by `DECAY_NUMERATOR / DECAY_DENOMINATOR` every `DECAY_RATE` ticks.
+const DECAY_NUMERATOR: u32 = 7;
+const DECAY_DENOMINATOR: u32 = 8;
+
+thread_local! {
+	/// Tiles stepped on this tick, waiting to be added to the heatmaps in memory.
+	static PENDING: RefCell<Vec<Position>> = const { RefCell::new(Vec::new()) };
+	/// Where each Creep was when it was last observed, and on which tick.
+	static LAST_SEEN: RefCell<HashMap<String, (Position, u32)>> = RefCell::new(HashMap::new());
+}
+
+/// How often each tile in a room has been walked over, decaying over time.
+/// Only tiles with any heat are stored.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Heatmap {
+	/// Heat by tile index, `x * 50 + y`.
+	tiles: BTreeMap<u16, u16>,
+}
+
+impl Heatmap {
+	pub fn get(&self, xy: RoomXY) -> u16 {
+		self.tiles.get(&index(xy)).copied().unwrap_or(0)
+	}
+
+	pub(crate) fn add(&mut self, xy: RoomXY) {
+		let heat = self.tiles.entry(index(xy)).or_insert(0);
+		*heat = heat.saturating_add(1);
+	}
+
+	fn decay(&mut self) {
+		self.tiles.retain(|_, heat| {
+			*heat = (*heat as u32 * DECAY_NUMERATOR / DECAY_DENOMINATOR) as u16;
+			*heat > 0
+		});
+	}
+
+	/// Every tile with at least `threshold` heat, hottest first.
+	pub fn hot_tiles(&self, threshold: u16) -> Vec<(RoomXY, u16)> {
+		let mut tiles: Vec<_> = self.tiles.iter()
+			.filter(|(_, heat)| **heat >= threshold)
+			.filter_map(|(&i, &heat)| Some((RoomXY::checked_new((i / 50) as u8, (i % 50) as u8).ok()?, heat)))
+			.collect();
+		tiles.sort_unstable_by_key(|(_, heat)| std::cmp::Reverse(*heat));
+		tiles
+	}
+}
+
+fn index(xy: RoomXY) -> u16 {
+	xy.x.u8() as u16 * 50 + xy.y.u8() as u16
+}
+
+/// Call once per tick for every Creep. Records the tile it's on if it has moved since the last tick,
+/// so only moves that actually happened add heat.
+pub fn observe(creep: &Creep) {
+	let pos = creep.pos();
+	let last = LAST_SEEN.with_borrow_mut(|last_seen| last_seen.insert(creep.name(), (pos, game::time())));
+	// Creeps that weren't seen last tick are new, or the heap was reset, so there's nothing to compare to.
+	if let Some((last_pos, tick)) = last && tick + 1 == game::time() && last_pos != pos {
+		PENDING.with_borrow_mut(|pending| pending.push(pos));
+	}
+}
+
+/// Adds everything recorded this tick to the heatmaps, and decays them when it's time.
+pub fn flush(heatmaps: &mut VecMap<RoomName, Heatmap>) {
+	// Creeps that weren't observed last tick have died or left sight.
+	let now = game::time();
+	LAST_SEEN.with_borrow_mut(|last_seen| last_seen.retain(|_, (_, tick)| *tick + 1 >= now));
+
+	for pos in PENDING.take() {
+		if let Some(heatmap) = heatmaps.get_mut(&pos.room_name()) {
+			heatmap.add(pos.xy());
+		} else {
+			let mut heatmap = Heatmap::default();
+			heatmap.add(pos.xy());
+			heatmaps.insert(pos.room_name(), heatmap);
+		}
+	}
+
+	if game::time() % DECAY_RATE == 0 {
+		for heatmap in heatmaps.values_mut() {
+			heatmap.decay();
+		}
+		heatmaps.retain(|_, heatmap| !heatmap.tiles.is_empty());
+	}
+}
@@ -0,0 +1,253 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::{logistics, mining, strategy::RoomStrategy, utils::prelude::*, JobEgg, JobFlag, JobInstance};
+
+thread_local! {
+	/// Average CPU spent by each provider per room, by name.
+	static PROVIDER_CPU: RefCell<HashMap<&'static str, ProviderStats>> = RefCell::new(HashMap::new());
+}
+
+/// Every job provider, in the order they're run.
+/// Later providers can depend on what earlier ones left in the [`RoomContext`].
+pub const PROVIDERS: &[&dyn JobProvider] = &[
+	&ConstructionJobs,
+	&MineJobs,
+	&HarvestJobs,
+	&UpgradeJobs,
+	&HaulJobs,
+];
+
+/// Everything a provider needs to know about the room it's generating jobs for.
+pub struct RoomContext<'a> {
+	pub room: &'a Room,
+	pub spawn: &'a StructureSpawn,
+	pub spawn_room: Option<&'a Room>,
+	pub strategy: &'static dyn RoomStrategy,
+	/// Filled in by [`HarvestJobs`].
+	pub harvester_jobs: u32,
+}
+
+/// Generates one kind of job for a room.
+pub trait JobProvider {
+	/// Used to enable and disable the provider per room.
+	fn name(&self) -> &'static str;
+
+	/// The kind of job generated, so strategies can turn it off.
+	fn job(&self) -> JobFlag;
+
+	/// Adds the room's jobs to `jobs`.
+	/// Returns how much they add to the room's desired population.
+	fn provide(&self, ctx: &mut RoomContext, jobs: &mut Vec<JobInstance>) -> usize;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProviderStats {
+	pub average_cpu: f64,
+	pub runs: u32,
+}
+
+/// Runs every provider the room's strategy wants, skipping those named in `disabled`.
+/// Returns the jobs and the room's desired population.
+pub fn generate(ctx: &mut RoomContext, disabled: &[String]) -> (Vec<JobInstance>, usize) {
+	let mut jobs = Vec::new();
+	let mut desired_pop = 0;
+
+	for provider in PROVIDERS {
+		if disabled.iter().any(|name| name == provider.name()) || !ctx.strategy.wants_jobs(provider.job()) {
+			continue;
+		}
+
+		let cpu = game::cpu::get_used();
+		desired_pop += provider.provide(ctx, &mut jobs);
+		let spent = game::cpu::get_used() - cpu;
+
+		log::trace!("Spent {spent} CPU on {} in room {}", provider.name(), ctx.room.name());
+		PROVIDER_CPU.with_borrow_mut(|stats| {
+			let stats = stats.entry(provider.name()).or_default();
+			stats.runs += 1;
+			// Moving average, so it reflects how the provider's been doing recently.
+			stats.average_cpu += (spent - stats.average_cpu) / stats.runs.min(100) as f64;
+		});
+	}
+
+	(jobs, desired_pop)
+}
+
+pub fn provider_stats() -> Vec<(&'static str, ProviderStats)> {
+	PROVIDER_CPU.with_borrow(|stats| {
+		PROVIDERS.iter().map(|p| (p.name(), stats.get(p.name()).copied().unwrap_or_default())).collect()
+	})
+}
+
+pub struct ConstructionJobs;
+
+impl JobProvider for ConstructionJobs {
+	fn name(&self) -> &'static str { "construction" }
+
+	fn job(&self) -> JobFlag { JobFlag::Construct }
+
+	fn provide(&self, ctx: &mut RoomContext, jobs: &mut Vec<JobInstance>) -> usize {
+		let mut desired_pop = 0;
+
+		let sites = ctx.room.find(screeps::find::MY_CONSTRUCTION_SITES, None);
+		for site in sites {
+			// This value was determined by extensive testing and heavy
+			// deliberation over multiple months by a panel of experts.
+			let requsted_creeps = (site.progress_total() - site.progress()).div_ceil(500);
+			(0..requsted_creeps).for_each(|i| {
+				jobs.push(JobInstance {
+					id: site.try_id().expect("Construction site doesn't have an ID").into_type(),
+					egg: JobEgg::Construct(site.clone()),
+					priority: 170 - i.min(160) as u8 * 13,
+				});
+
+				desired_pop += 1;
+			});
+		}
+
+		desired_pop
+	}
+}
+
+/// One miner for every source with somewhere to put what it mines.
+pub struct MineJobs;
+
+impl JobProvider for MineJobs {
+	fn name(&self) -> &'static str { "mine" }
+
+	fn job(&self) -> JobFlag { JobFlag::Mine }
+
+	fn provide(&self, ctx: &mut RoomContext, jobs: &mut Vec<JobInstance>) -> usize {
+		if !mining::can_support_miners(ctx.room) {
+			return 0;
+		}
+
+		let mut desired_pop = 0;
+
+		for source in ctx.room.find(screeps::find::SOURCES, None) {
+			if mining::spot(&source).is_none() {
+				continue;
+			}
+
+			jobs.push(JobInstance {
+				id: source.id().into_type(),
+				egg: JobEgg::Mine(source),
+				priority: 255,
+			});
+
+			desired_pop += 1;
+		}
+
+		desired_pop
+	}
+}
+
+pub struct HarvestJobs;
+
+impl JobProvider for HarvestJobs {
+	fn name(&self) -> &'static str { "harvest" }
+
+	fn job(&self) -> JobFlag { JobFlag::Harvest }
+
+	fn provide(&self, ctx: &mut RoomContext, jobs: &mut Vec<JobInstance>) -> usize {
+		let mut desired_pop = 0;
+
+		let terrain = ctx.room.get_terrain();
+		let spawn_near_full = ctx.spawn.store().get_free_capacity(None) <= 150;
+		for source in ctx.room.find(screeps::find::SOURCES_ACTIVE, None) {
+			let mined = mining::is_mined(&source);
+			let pos: Position = source.pos();
+			let mut valid_dir_count = 0u8;
+			for dir in screeps::Direction::iter() {
+				if let Ok(pos) = pos.checked_add_direction(*dir) && terrain.get_xy(pos.xy()) != screeps::Terrain::Wall {
+					// A miner takes care of it, but the energy is still coming in for upgraders to use.
+					if mined {
+						ctx.harvester_jobs += 1;
+						continue;
+					}
+
+					let instance = JobInstance {
+						id: source.id().into_type(),
+						egg: JobEgg::Harvest(source.clone(), ctx.spawn.clone().into()),
+						priority: 250 - valid_dir_count * 30,
+					};
+					let second_instance = JobInstance {
+						priority: 120 - valid_dir_count * 30,
+						..instance.clone()
+					};
+					jobs.push(instance);
+					if spawn_near_full {
+						jobs.push(second_instance);
+					}
+
+					ctx.harvester_jobs += 1;
+					valid_dir_count += 1;
+
+					desired_pop += 2;
+				}
+			}
+		}
+
+		desired_pop
+	}
+}
+
+pub struct UpgradeJobs;
+
+impl JobProvider for UpgradeJobs {
+	fn name(&self) -> &'static str { "upgrade" }
+
+	fn job(&self) -> JobFlag { JobFlag::Upgrade }
+
+	fn provide(&self, ctx: &mut RoomContext, jobs: &mut Vec<JobInstance>) -> usize {
+		let mut desired_pop = 0;
+
+		if let Some(spawn_room) = ctx.spawn_room && ctx.room == spawn_room && let Some(controller) = spawn_room.controller() {
+			for i in 0..ctx.strategy.max_upgraders(ctx.harvester_jobs) {
+				jobs.push(JobInstance {
+					id: controller.id().into_type(),
+					egg: JobEgg::Upgrade(controller.clone()),
+					priority: 200 - i.min(200) as u8 * 13,
+				});
+
+				desired_pop += 1;
+			}
+		}
+
+		desired_pop
+	}
+}
+
+pub struct HaulJobs;
+
+impl HaulJobs {
+	/// Energy a single hauler is expected to move per trip.
+	const TRIP_SIZE: u32 = 500;
+	/// The most haulers sent to a single offer at once.
+	const MAX_PER_OFFER: u32 = 3;
+}
+
+impl JobProvider for HaulJobs {
+	fn name(&self) -> &'static str { "haul" }
+
+	fn job(&self) -> JobFlag { JobFlag::Haul }
+
+	fn provide(&self, ctx: &mut RoomContext, jobs: &mut Vec<JobInstance>) -> usize {
+		let mut desired_pop = 0;
+
+		for offer in logistics::open_offers(ctx.room) {
+			let Some(structure) = offer.target.resolve() else { continue };
+			for i in 0..offer.available().div_ceil(Self::TRIP_SIZE).min(Self::MAX_PER_OFFER) {
+				jobs.push(JobInstance {
+					id: offer.target.into_type(),
+					egg: JobEgg::Haul(structure.clone()),
+					priority: 180 - i as u8 * 20,
+				});
+
+				desired_pop += 1;
+			}
+		}
+
+		desired_pop
+	}
+}
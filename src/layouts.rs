@@ -0,0 +1,71 @@
+use std::cell::{Cell, RefCell};
+
+use screeps::RoomName;
+use vecmap::VecMap;
+
+use crate::{room_planner::RoomLayout, segments};
+
+thread_local! {
+	/// Every room's planned layout, once they've been loaded from their segments.
+	static LAYOUTS: RefCell<Option<VecMap<RoomName, RoomLayout>>> = const { RefCell::new(None) };
+	/// Whether a layout has changed since they were last written to their segments.
+	static DIRTY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Call once per tick, after segments have been picked up.
+/// Loads the layouts after a reset, and writes them back when one has changed.
+pub fn tick() {
+	if !loaded() {
+		let Some(decoded) = segments::read_decoded(segments::LAYOUTS) else {
+			// The segments haven't loaded yet, try again next tick.
+			return;
+		};
+		let layouts = decoded.unwrap_or_else(|err| {
+			log::error!("Failed to load room layouts, rooms will be planned again: '{err}'");
+			VecMap::new()
+		});
+		LAYOUTS.set(Some(layouts));
+	}
+
+	if DIRTY.get() {
+		save();
+	}
+}
+
+/// Whether the layouts have been loaded. Until then, no room should be planned.
+pub fn loaded() -> bool {
+	LAYOUTS.with_borrow(Option::is_some)
+}
+
+pub fn get(room: RoomName) -> Option<RoomLayout> {
+	LAYOUTS.with_borrow(|layouts| layouts.as_ref()?.get(&room).cloned())
+}
+
+pub fn contains(room: RoomName) -> bool {
+	LAYOUTS.with_borrow(|layouts| layouts.as_ref().is_some_and(|layouts| layouts.contains_key(&room)))
+}
+
+/// Replaces the room's layout. Ignored until the layouts have been loaded.
+pub fn set(room: RoomName, layout: RoomLayout) {
+	LAYOUTS.with_borrow_mut(|layouts| {
+		if let Some(layouts) = layouts {
+			layouts.insert(room, layout);
+			DIRTY.set(true);
+		}
+	});
+}
+
+fn save() {
+	let result = LAYOUTS.with_borrow(|layouts| {
+		let entries: Vec<_> = layouts.iter().flatten().collect();
+		segments::write_map(segments::LAYOUTS, &entries)
+	});
+
+	// Nothing is retried until a layout changes again, so errors aren't repeated every tick.
+	DIRTY.set(false);
+	match result {
+		Ok(0) => {}
+		Ok(left_out) => log::error!("{left_out} room layouts didn't fit in their segments and will be planned again after a reset"),
+		Err(err) => log::error!("Failed to save room layouts: '{err}'"),
+	}
+}
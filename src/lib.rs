@@ -13,7 +13,28 @@ pub(crate) mod cmds;
 pub(crate) mod memory;
 pub(crate) mod state;
 pub(crate) mod dynamic_stuff;
+pub(crate) mod pathing;
+pub(crate) mod path_cache;
+pub(crate) mod cost_matrix;
+pub(crate) mod traffic;
+pub(crate) mod heatmap;
+pub(crate) mod road_planner;
+pub(crate) mod room_planner;
+pub(crate) mod blueprint;
+pub(crate) mod strategy;
+pub(crate) mod jobs;
+pub(crate) mod economy;
+pub(crate) mod logistics;
+pub(crate) mod reservations;
+pub(crate) mod roles;
+pub(crate) mod mining;
+pub(crate) mod spots;
+pub(crate) mod graveyard;
+pub(crate) mod segments;
+pub(crate) mod layouts;
+pub(crate) mod stats;
 pub mod quotes;
+pub mod offline;
 
 use wasm_bindgen::prelude::*;
 use utils::prelude::*;
@@ -35,103 +56,84 @@ pub fn game_loop() {
 	let total_cpu = screeps::game::cpu::get_used();
 
 	let cpu = screeps::game::cpu::get_used();
-	let mut global_memory = memory::get_memory();
+	let mut global_memory = memory::load();
 	log::trace!("Spent {} CPU on memory access", screeps::game::cpu::get_used() - cpu);
 
+	graveyard::collect(&mut global_memory);
+	reservations::load(&global_memory);
+
+	segments::tick();
+	layouts::tick();
+	cost_matrix::tick();
+	path_cache::tick();
+	spots::tick();
+
 	let spawn = game::spawns().values().next().unwrap();
 	let spawn_room = spawn.room();
 
 	let mut creep_count = game::creeps().keys().count();
-	
+
 	// Holds Creeps without jobs, to be arranged after all other Creeps are dispatched.
 	let mut creep_queue = Vec::new();
 
 	let room_cpu = screeps::game::cpu::get_used();
 
-	for room in game::rooms().values() {
-		let job_cpu = screeps::game::cpu::get_used();
-
-		let sources = room.find(screeps::find::SOURCES_ACTIVE, None);
-		let mut room_jobs = Vec::with_capacity(sources.len() * 2);
+	heatmap::flush(&mut global_memory.heatmaps);
 
-		// This is primarily based on the number of 'constant' jobs such as upgrading and harvesting.
-		// This number acts as a baseline for other things.
-		// It is slightly inflated by things such as redundant harvesters and a large
-		// number of upgraders. This allows for allocation of builders and the like without
-		// factoring them into overall population. At the end of job allocation, this number
-		// is used to determine whether or not to spawn additional Creeps.
-		let mut desired_pop = 0;
-		
-		// Construction jobs.
-		let sites = room.find(screeps::find::MY_CONSTRUCTION_SITES, None);
-		for site in sites {
-			// This value was determined by extensive testing and heavy
-			// deliberation over multiple months by a panel of experts.
-			let requsted_creeps = (site.progress_total() - site.progress()).div_ceil(500);
-			(0..requsted_creeps).for_each(|i| {
-				room_jobs.push(JobInstance {
-					id: site.try_id().expect("Construction site doesn't have an ID").into_type(),
-					egg: JobEgg::Construct(site.clone()),
-					priority: 170 - i.min(160) as u8 * 13,
-				});
+	for room in game::rooms().values() {
+		cost_matrix::refresh(&room);
 
-				desired_pop += 1;
-			});
+		if let Some(heatmap) = global_memory.heatmaps.get(&room.name()) {
+			road_planner::plan_roads(&room, heatmap);
 		}
 
-		// Harvester jobs.
-		let mut harvester_jobs = 0u32;
-		let mut terrain = room.get_terrain();
-		let spawn_near_full = spawn.store().get_free_capacity(None) <= 150;
-		for source in sources {
-			let pos: Position = source.pos();
-			let mut valid_dir_count = 0u8;
-			for dir in screeps::Direction::iter() {
-				if let Ok(pos) = pos.checked_add_direction(*dir) && terrain.get_xy(pos.xy()) != screeps::Terrain::Wall {
-					let instance = JobInstance {
-						id: source.id().into_type(),
-						egg: JobEgg::Harvest(source.clone(), spawn.clone().into()),
-						priority: 250 - valid_dir_count * 30,
-					};
-					let second_instance = JobInstance {
-						priority: 120 - valid_dir_count * 30,
-						..instance.clone()
-					};
-					room_jobs.push(instance);
-					if spawn_near_full {
-						room_jobs.push(second_instance);
-					}
-
-					harvester_jobs += 1;
-					valid_dir_count += 1;
-
-					desired_pop += 2;
-				}
+		if room.controller().is_some_and(|c| c.my()) {
+			if global_memory.rooms.get(&room.name()).is_none() {
+				global_memory.rooms.insert(room.name(), Default::default());
+				memory::mark_dirty();
 			}
+			room_planner::run(&room, global_memory.rooms.get_mut(&room.name()).unwrap());
+			mining::place_containers(&room);
 		}
 
-		// Upgrader jobs.
-		if let Some(spawn_room) = &spawn_room && room == *spawn_room && let Some(controller) = spawn_room.controller() {
-			for i in 0..harvester_jobs.div_ceil(2).min(20) {
-				room_jobs.push(JobInstance {
-					id: controller.id().into_type(),
-					egg: JobEgg::Upgrade(controller.clone()),
-					priority: 200 - i.min(200) as u8 * 13,
-				});
+		let strategy = strategy::select(&room);
+		log::trace!("Room {} is using strategy {}", room.name(), strategy.name());
 
-				desired_pop += 1;
-			}
-		}
+		logistics::post_room(&room);
+
+		let job_cpu = screeps::game::cpu::get_used();
+
+		// The desired population is primarily based on the number of 'constant' jobs such as upgrading and harvesting.
+		// This number acts as a baseline for other things.
+		// It is slightly inflated by things such as redundant harvesters and a large
+		// number of upgraders. The strategy turns it into the most Creeps the room will spawn,
+		// while the room's economy decides whether each one is worth spawning.
+		let mut job_ctx = jobs::RoomContext {
+			room: &room,
+			spawn: &spawn,
+			spawn_room: spawn_room.as_ref(),
+			strategy,
+			harvester_jobs: 0,
+		};
+		let disabled = global_memory.rooms.get(&room.name()).map(|r| r.disabled_providers.as_slice()).unwrap_or_default();
+		let (mut room_jobs, desired_pop) = jobs::generate(&mut job_ctx, disabled);
 
 		log::trace!("Spent {} CPU on room jobs", screeps::game::cpu::get_used() - job_cpu);
 		let creep_cpu = screeps::game::cpu::get_used();
 
 		for creep in room.find(screeps::find::MY_CREEPS, None) {
-			let Some(mut creep_data) = global_memory.creep_data.get_mut(&conto!(creep.try_id())) else {
-				global_memory.creep_data.insert(conto!(creep.try_id()), Default::default());
+			if creep.spawning() {
+				continue;
+			}
+			heatmap::observe(&creep);
+
+			let Some(mut creep_data) = global_memory.creep_data.get_mut(&creep.name()) else {
+				global_memory.creep_data.insert(creep.name(), Default::default());
+				memory::mark_dirty();
 				creep_queue.push(creep);
 				continue;
 			};
+			graveyard::observe(&creep, creep_data);
 			
 			let Some((job, mut state)) = creep_data.current_task.take() else {
 				creep_queue.push(creep);
@@ -149,7 +151,7 @@ pub fn game_loop() {
 				continue;
 			}
 
-			if let Some(i) = room_jobs.iter().position(|id| job.id ==  id.id.into_type()) {
+			if let Some(i) = room_jobs.iter().position(|id| job.job == JobFlag::from(&id.egg) && job.id == id.id.into_type()) {
 				room_jobs.remove(i);
 			}
 
@@ -160,12 +162,16 @@ pub fn game_loop() {
 				StateResult::Finished(r) => {
 					ign!(creep.say(":)", true));
 					log::info!("Creep {} finished task {:?} - {r:?}", creep.name(), state.flag);
+					*creep_data.jobs_finished.entry(job.job).or_insert(0) += 1;
+					memory::mark_dirty();
 					creep_queue.push(creep);
 					new_idle()
 				}
 				StateResult::Failed(e) => {
 					ign!(creep.say(":(", true));
 					log::warn!("Creep {} failed to complete task: {:?} - {e:?}", creep.name(), state.flag);
+					*creep_data.jobs_failed.entry(job.job).or_insert(0) += 1;
+					memory::mark_dirty();
 					creep_queue.push(creep);
 					new_idle()
 				}
@@ -182,23 +188,28 @@ pub fn game_loop() {
 		// fastrand::shuffle(&mut room_jobs);
 		for creep in creep_queue.drain(..) {
 			// We ensure each Creep has a data entry above.
-			let creep_data = global_memory.creep_data.get_mut(&conto!(creep.try_id())).unwrap();
+			let creep_data = global_memory.creep_data.get_mut(&creep.name()).unwrap();
+			let role = creep_data.role.unwrap_or_else(|| roles::Role::of(&creep));
 
 			let job = if let Some(to_live) = creep.ticks_to_live() && to_live < 120 {
 				log::debug!("Assigning creep {} to seppuku", creep.name());
+				creep_data.seppuku = true;
 				JobInstance {
 					egg: JobEgg::Seppuku,
-					id: creep.try_id().unwrap().into_type(),
+					id: RoomObjectId::from_packed(0),
 					priority: 255,
 				}
-			} else if let Some(job) = room_jobs.pop() {
-				job
+			} else if let Some(i) = creep_data.planned_job.take()
+				.and_then(|planned| room_jobs.iter().rposition(|j| JobFlag::from(&j.egg) == planned))
+				.or_else(|| room_jobs.iter().rposition(|j| role.can_take(JobFlag::from(&j.egg)))) {
+				room_jobs.remove(i)
 			} else {
 				creep_data.current_task = Some(new_idle());
 				continue;
 			};
 
 			log::info!("Creep {} assigned to job {:?}", creep.name(), JobFlag::from(&job.egg));
+			memory::mark_dirty();
 
 			// creep_data.current_task = Some(state::harvester_graph::HarvesterStateGraph::new(&creep, job.id.into_type(), AsRef::<Structure>::as_ref(&spawn).id(), job.id.into_type()).into());
 			creep_data.current_task = Some(match job.egg {
@@ -218,15 +229,55 @@ pub fn game_loop() {
 					let state = state::builder::StateBuilderJob::new(&creep, site.try_id().expect("Construction site doesn't have an ID"), spawn.id().into_type());
 					(job.egg.into(), DynState::new(state, dynamic_stuff::StateFlag::BuilderJob))
 				}
+				JobEgg::Haul(ref offer) => {
+					let Some(state) = state::hauler::StateHaulerJob::new(&creep, offer.id()) else {
+						creep_data.current_task = Some(new_idle());
+						continue;
+					};
+					(job.egg.into(), DynState::new(state, dynamic_stuff::StateFlag::HaulerJob))
+				}
+				JobEgg::Mine(ref source) => {
+					let Some(spot) = mining::spot(source) else {
+						creep_data.current_task = Some(new_idle());
+						continue;
+					};
+					let state = state::miner::StateMinerJob::new(&creep, source.id(), spot);
+					(job.egg.into(), DynState::new(state, dynamic_stuff::StateFlag::MinerJob))
+				}
 			});
 		}
 
 		let mut used: Vec<_> = screeps::game::creeps().keys().collect();
 
-		if creep_count < desired_pop {
-			use screeps::Part::*;
+		// Whatever's left in the job list couldn't be filled by the Creeps we have.
+		let unfilled = |flag: JobFlag| room_jobs.iter().any(|j| JobFlag::from(&j.egg) == flag);
+
+		// Miners and haulers are spawned whenever their jobs are open and the room can afford them.
+		// Other Creeps are spawned while the room isn't harvesting all it could, as long as there are jobs for them to take.
+		let room_economy = spawn_room.as_ref().map(|spawn_room| economy::estimate(spawn_room, &global_memory.creep_data));
+		let affordable = |body: &Vec<screeps::Part>| room_economy.is_some_and(|e| e.affords(&economy::WorkRates::from_body(body.iter().copied())));
+		let spawn_plan = if let Some(spawn_room) = &spawn_room && unfilled(JobFlag::Mine) {
+			roles::miner_body(spawn_room).filter(affordable).map(|body| (body, Some(JobFlag::Mine)))
+		} else if let Some(spawn_room) = &spawn_room && unfilled(JobFlag::Haul) && room.find(screeps::find::MY_CREEPS, None).iter().any(|c| roles::Role::of(c) == roles::Role::Miner) {
+			roles::hauler_body(spawn_room).filter(affordable).map(|body| (body, Some(JobFlag::Haul)))
+		} else if let Some(spawn_room) = &spawn_room && let Some(body) = strategy.spawn_body(spawn_room)
+			&& room_economy.is_some_and(|e| e.wants_creep(&economy::WorkRates::from_body(body.iter().copied()))) {
+			Some((body, None))
+		} else {
+			None
+		};
+
+		if creep_count < strategy.population_target(desired_pop) && let Some((body, planned_job)) = spawn_plan {
 			let name = utils::get_new_creep_name(&used);
-			ign!(spawn.spawn_creep(&[Move, Move, Work, Carry, Carry], &utils::generate_name()));
+			if spawn.spawn_creep(&body, &name).is_ok() {
+				// Made now so the Creep takes the job it was spawned for once it's out.
+				global_memory.creep_data.insert(name.clone(), memory::CreepData {
+					role: Some(roles::Role::from_body(body.iter().copied())),
+					planned_job,
+					..Default::default()
+				});
+				memory::mark_dirty();
+			}
 			used.push(name);
 		}
 
@@ -236,9 +287,18 @@ pub fn game_loop() {
 	}
 
 	let cpu = screeps::game::cpu::get_used();
-	memory::set_memory(&global_memory);
+	traffic::resolve();
+	log::trace!("Spent {} CPU on traffic", screeps::game::cpu::get_used() - cpu);
+
+	reservations::save(&mut global_memory);
+
+	let cpu = screeps::game::cpu::get_used();
+	memory::store(global_memory);
 	log::trace!("Spent {} CPU on memory save", screeps::game::cpu::get_used() - cpu);
 
+	stats::record();
+	logging::save_logs();
+
 	log::debug!("CPU used during tick: {}", screeps::game::cpu::get_used() - total_cpu);
 }
 
@@ -257,6 +317,8 @@ impl From<JobEgg> for JobIdentifier {
 				JobEgg::Harvest(ref source, _) => source.id().into_type(),
 				JobEgg::Upgrade(ref controller) => controller.id().into_type(),
 				JobEgg::Construct(ref site) => site.try_id().expect("Construction site doesn't have an ID").into_type(),
+				JobEgg::Haul(ref offer) => offer.id().into_type(),
+				JobEgg::Mine(ref source) => source.id().into_type(),
 			},
 			job: job.into(),
 		}
@@ -277,6 +339,9 @@ enum JobEgg {
 	Harvest(Source, Structure),
 	Upgrade(StructureController),
 	Construct(ConstructionSite),
+	/// Takes from the offer in the logistics network.
+	Haul(Structure),
+	Mine(Source),
 	Seppuku,
 	//? Is kinda used as a fallback tag? It's not real.
 	#[allow(dead_code)] Idle,
@@ -1,4 +1,4 @@
-use std::{fmt::Write, panic};
+use std::{cell::RefCell, fmt::Write, panic};
 
 use js_sys::JsString;
 use log::*;
@@ -8,8 +8,20 @@ use web_sys::console;
 
 pub use log::LevelFilter::*;
 
+use crate::segments;
+
+/// Lines kept while the logs segments are loading, beyond which the oldest are dropped.
+const MAX_PENDING_LINES: usize = 500;
+
+thread_local! {
+    /// Warnings and errors logged since they were last added to the logs segments.
+    static PENDING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
 struct JsLog;
 struct JsNotify;
+/// Keeps warnings and errors to be saved to the logs segments.
+struct SegmentLog;
 
 impl log::Log for JsLog {
     fn enabled(&self, _: &log::Metadata<'_>) -> bool {
@@ -30,18 +42,42 @@ impl log::Log for JsNotify {
     fn flush(&self) {}
 }
 
+impl log::Log for SegmentLog {
+    fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record<'_>) {
+        PENDING.with_borrow_mut(|pending| {
+            if pending.len() >= MAX_PENDING_LINES {
+                pending.remove(0);
+            }
+            pending.push(format!("[{}] {}: {}", game::time(), record.level(), record.args()));
+        });
+    }
+    fn flush(&self) {}
+}
+
 pub fn setup_logging(verbosity: log::LevelFilter) {
     fern::Dispatch::new()
         .level(verbosity)
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                r#"<span style="color:orange">{}</span>: {}"#,
-                record.level(),
-                // record.target(),
-                message
-            ))
-        })
-        .chain(Box::new(JsLog) as Box<dyn log::Log>)
+        .chain(
+            fern::Dispatch::new()
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        r#"<span style="color:orange">{}</span>: {}"#,
+                        record.level(),
+                        // record.target(),
+                        message
+                    ))
+                })
+                .chain(Box::new(JsLog) as Box<dyn log::Log>),
+        )
+        // Warnings and errors are also kept in segments, without the console's markup.
+        .chain(
+            fern::Dispatch::new()
+                .level(Warn)
+                .chain(Box::new(SegmentLog) as Box<dyn log::Log>),
+        )
         .apply()
         .expect("expected setup_logging to only ever be called once per instance");
     panic::set_hook(Box::new(panic_hook));
@@ -61,6 +97,45 @@ extern "C" {
     fn stack_trace_limit(size: f32);
 }
 
+/// Call once at the end of the tick. Adds what was logged to the logs segments,
+/// dropping the oldest lines once they're full.
+pub fn save_logs() {
+    let pending = PENDING.take();
+    if pending.is_empty() {
+        return;
+    }
+
+    let Some(mut log) = segments::read(segments::LOGS) else {
+        // The segments haven't loaded yet, so the lines wait until they have.
+        PENDING.with_borrow_mut(|newer| {
+            let mut lines = pending;
+            lines.append(newer);
+            *newer = lines;
+        });
+        return;
+    };
+
+    for line in pending {
+        log.push_str(&line);
+        log.push('\n');
+    }
+
+    let capacity = segments::LOGS.capacity();
+    if log.len() > capacity {
+        let mut start = log.len() - capacity;
+        while !log.is_char_boundary(start) {
+            start += 1;
+        }
+        // Whole lines are dropped, so the log never starts partway through one.
+        let start = log[start..].find('\n').map_or(log.len(), |i| start + i + 1);
+        log.drain(..start);
+    }
+
+    if let Err(err) = segments::write(segments::LOGS, &log) {
+        warn!("Failed to save logs: '{err}'");
+    }
+}
+
 fn panic_hook(info: &panic::PanicHookInfo) {
     // import JS Error API to get backtrace info (backtraces don't work in wasm)
     // Node 8 does support this API: https://nodejs.org/docs/latest-v8.x/api/errors.html#errors_error_stack
@@ -0,0 +1,213 @@
+use std::cell::RefCell;
+
+use screeps::{find, HasStore, ResourceType, StructureObject, StructureType};
+
+use crate::{reservations::{self, Flow}, utils::prelude::*};
+
+thread_local! {
+	static NETWORK: RefCell<Network> = RefCell::new(Network::default());
+}
+
+/// Offers or requests below this amount aren't worth a trip.
+const MIN_AMOUNT: u32 = 50;
+/// Towers below this share of their capacity ask for energy urgently.
+const TOWER_LOW: f64 = 0.5;
+
+/// How urgently something should be filled or emptied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum Priority {
+	/// Only worth it if the other end actually needs it.
+	Low,
+	Normal,
+	High,
+	Critical,
+}
+
+/// Energy available to take, or space wanting to be filled.
+#[derive(Clone, Debug)]
+pub struct Entry {
+	pub target: StructureId,
+	pub pos: Position,
+	pub resource: ResourceType,
+	pub amount: u32,
+	pub priority: Priority,
+	/// Outgoing for offers, incoming for requests.
+	flow: Flow,
+}
+
+impl Entry {
+	/// What's left once everything Creeps have reserved in the ledger is taken out.
+	pub fn available(&self) -> u32 {
+		self.amount.saturating_sub(reservations::reserved(self.target, self.resource, self.flow))
+	}
+
+	fn claim(&self, creep: &Creep, amount: u32) -> Claim {
+		let amount = amount.min(self.available());
+		reservations::reserve(creep, self.target, self.resource, self.flow, amount);
+		Claim { target: self.target, amount }
+	}
+}
+
+/// Some amount of an offer or request promised to a single Creep.
+/// The Creep keeps it by reserving it in the ledger every tick until it's done.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Claim {
+	pub target: StructureId,
+	pub amount: u32,
+}
+
+impl Claim {
+	/// Keeps the claim for another tick. Call every tick until the Creep is done with it.
+	pub fn hold(&self, creep: &Creep, resource: ResourceType, flow: Flow) {
+		reservations::reserve(creep, self.target, resource, flow, self.amount);
+	}
+
+	/// Gives the claim up so other Creeps can have it.
+	pub fn release(&self, creep: &Creep) {
+		reservations::release(creep, self.target);
+	}
+}
+
+#[derive(Default)]
+struct Network {
+	tick: u32,
+	offers: Vec<Entry>,
+	requests: Vec<Entry>,
+}
+
+impl Network {
+	/// Clears last tick's entries the first time the network is used on a new tick.
+	fn current(&mut self) -> &mut Self {
+		if self.tick != game::time() {
+			*self = Network { tick: game::time(), ..Default::default() };
+		}
+		self
+	}
+}
+
+/// Posts energy available to be taken from `target`.
+pub fn offer(target: StructureId, pos: Position, resource: ResourceType, amount: u32, priority: Priority) {
+	NETWORK.with_borrow_mut(|network| {
+		network.current().offers.push(Entry { target, pos, resource, amount, priority, flow: Flow::Outgoing });
+	});
+}
+
+/// Posts space in `target` wanting to be filled.
+pub fn request(target: StructureId, pos: Position, resource: ResourceType, amount: u32, priority: Priority) {
+	NETWORK.with_borrow_mut(|network| {
+		network.current().requests.push(Entry { target, pos, resource, amount, priority, flow: Flow::Incoming });
+	});
+}
+
+/// Posts offers and requests for every structure in the room that stores energy.
+pub fn post_room(room: &Room) {
+	for structure in room.find(find::MY_STRUCTURES, None) {
+		let Some(store) = structure.as_has_store().map(|s| s.store()) else { continue };
+		let used = store.get_used_capacity(Some(ResourceType::Energy));
+		let free = store.get_free_capacity(Some(ResourceType::Energy)).max(0) as u32;
+		let id = structure.as_structure().id();
+		let pos = structure.pos();
+
+		match structure.structure_type() {
+			StructureType::Spawn | StructureType::Extension => {
+				request(id, pos, ResourceType::Energy, free, Priority::Critical);
+				// Creeps have always been able to take from the spawn, so it stays an option of last resort.
+				if structure.structure_type() == StructureType::Spawn {
+					offer(id, pos, ResourceType::Energy, used, Priority::Low);
+				}
+			}
+			StructureType::Tower => {
+				let capacity = store.get_capacity(Some(ResourceType::Energy));
+				let priority = if (used as f64) < capacity as f64 * TOWER_LOW { Priority::High } else { Priority::Normal };
+				request(id, pos, ResourceType::Energy, free, priority);
+			}
+			StructureType::Storage => {
+				request(id, pos, ResourceType::Energy, free, Priority::Low);
+				offer(id, pos, ResourceType::Energy, used, Priority::Low);
+			}
+			StructureType::Link => {
+				offer(id, pos, ResourceType::Energy, used, Priority::Normal);
+			}
+			_ => {}
+		}
+	}
+
+	// Containers are never owned, so they aren't in the search above.
+	for structure in room.find(find::STRUCTURES, None) {
+		if let StructureObject::StructureContainer(container) = structure {
+			let used = container.store().get_used_capacity(Some(ResourceType::Energy));
+			offer(container.id().into_type(), container.pos(), ResourceType::Energy, used, Priority::Normal);
+		}
+	}
+}
+
+/// The most urgent, then closest, entry with anything left unclaimed.
+fn best(entries: &[Entry], pos: Position, resource: ResourceType) -> Option<&Entry> {
+	entries.iter()
+		.filter(|e| e.resource == resource && e.available() >= MIN_AMOUNT)
+		.min_by_key(|e| (std::cmp::Reverse(e.priority), range(pos, e.pos)))
+}
+
+fn range(a: Position, b: Position) -> u32 {
+	if a.room_name() == b.room_name() { a.get_range_to(b) } else { u32::MAX }
+}
+
+/// Claims up to `amount` from the best offer for the Creep.
+pub fn claim_offer(creep: &Creep, resource: ResourceType, amount: u32) -> Option<Claim> {
+	NETWORK.with_borrow_mut(|network| {
+		Some(best(&network.current().offers, creep.pos(), resource)?.claim(creep, amount))
+	})
+}
+
+/// Claims space for up to `amount` in the best request for the Creep.
+pub fn claim_request(creep: &Creep, resource: ResourceType, amount: u32) -> Option<Claim> {
+	NETWORK.with_borrow_mut(|network| {
+		Some(best(&network.current().requests, creep.pos(), resource)?.claim(creep, amount))
+	})
+}
+
+/// Matches a hauler with `capacity` free to an offer and the request it should fill,
+/// only considering the offer from `from` if given.
+/// Pairs where neither end cares much are skipped, so haulers don't shuffle energy
+/// between storage and the spawn.
+pub fn claim_pair(creep: &Creep, resource: ResourceType, capacity: u32, from: Option<StructureId>) -> Option<(Claim, Claim)> {
+	NETWORK.with_borrow_mut(|network| {
+		let network = network.current();
+		let pos = creep.pos();
+		let request = best(&network.requests, pos, resource)?;
+
+		let offer = network.offers.iter()
+			.filter(|e| e.resource == resource && e.available() >= MIN_AMOUNT && e.target != request.target)
+			.filter(|e| request.priority > Priority::Low || e.priority > Priority::Low)
+			.filter(|e| from.is_none_or(|from| e.target == from))
+			.min_by_key(|e| (std::cmp::Reverse(e.priority), range(pos, e.pos).saturating_add(range(e.pos, request.pos))))?;
+
+		let offer = offer.claim(creep, capacity.min(request.available()));
+		let request = request.claim(creep, offer.amount);
+		Some((offer, request))
+	})
+}
+
+/// Offers with enough left unclaimed to be worth sending haulers for.
+pub fn open_offers(room: &Room) -> Vec<Entry> {
+	NETWORK.with_borrow_mut(|network| {
+		network.current().offers.iter()
+			.filter(|e| e.pos.room_name() == room.name() && e.priority > Priority::Low && e.available() >= MIN_AMOUNT)
+			.cloned()
+			.collect()
+	})
+}
+
+/// Claims energy for the Creep to fill up on, or None if nothing's on offer.
+/// The claim is held with [`Flow::Outgoing`] until the energy is taken.
+pub fn energy_source(creep: &Creep) -> Option<Claim> {
+	let free = creep.store().get_free_capacity(Some(ResourceType::Energy)).max(0) as u32;
+	claim_offer(creep, ResourceType::Energy, free)
+}
+
+/// Claims space for the energy the Creep is carrying, or None if nothing needs it.
+/// The claim is held with [`Flow::Incoming`] until the energy is delivered.
+pub fn energy_sink(creep: &Creep) -> Option<Claim> {
+	let carried = creep.store().get_used_capacity(Some(ResourceType::Energy));
+	claim_request(creep, ResourceType::Energy, carried)
+}
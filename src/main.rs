@@ -1,5 +1,110 @@
+//! Inspects memory outside of the game, such as from a server export.
+
+use std::{io::Read, sync::atomic::{AtomicUsize, Ordering}};
+
+use screepies::offline::{self, Format, Memory, MemoryCodec, FORMAT, SCHEMA_VERSION};
+
+const USAGE: &str = "\
+Usage: screepies <command> [--format <format>] [file]
+
+Reads from `file`, or stdin if it isn't given, and writes to stdout.
+
+Commands:
+    decode      Base64 memory to JSON, migrating it if it's out of date
+    report      Base64 memory to tables of what's in it and how large it is
+    validate    Checks that base64 memory reads as the current layout
+    encode      JSON to base64 memory
+    migrate     Base64 memory to base64 memory in the current schema
+
+Formats are rmp-named, rmp-compact, bitcode and json, with +deflate to compress.
+Memory is written in the format the bot uses unless one is given.";
+
+/// Errors logged while reading memory, such as Creep entries that had to be reset.
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
 fn main() {
-	setup_logging(log::LevelFilter::Trace);
+	setup_logging(log::LevelFilter::Info);
+
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	if let Err(err) = run(&args) {
+		eprintln!("{err}");
+		std::process::exit(1);
+	}
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+	let (command, rest) = args.split_first().ok_or(USAGE)?;
+
+	let mut format = FORMAT;
+	let mut file = None;
+	let mut rest = rest.iter();
+	while let Some(arg) = rest.next() {
+		if arg == "--format" {
+			format = parse_format(rest.next().ok_or("Missing format after --format")?)?;
+		} else {
+			file = Some(arg.as_str());
+		}
+	}
+	let input = read_input(file)?;
+
+	match command.as_str() {
+		"decode" => println!("{}", decode(&input)?.to_json()?),
+		"report" => println!("{}", decode(&input)?.report()?),
+		"validate" => {
+			decode(&input)?;
+			let errors = ERRORS.load(Ordering::Relaxed);
+			if errors > 0 {
+				return Err(format!("Memory read with {errors} errors, see above"));
+			}
+			println!("Memory is valid for schema version {SCHEMA_VERSION}");
+		}
+		"encode" => {
+			let memory = Memory::from_json(&input)?;
+			println!("{}", offline::encode_base64(&memory.encode(format)?));
+		}
+		"migrate" => {
+			let memory = decode(&input)?;
+			println!("{}", offline::encode_base64(&memory.encode(format)?));
+		}
+		_ => return Err(USAGE.to_string()),
+	}
+
+	Ok(())
+}
+
+fn read_input(file: Option<&str>) -> Result<String, String> {
+	match file {
+		Some(file) => std::fs::read_to_string(file).map_err(|e| format!("Failed to read '{file}': {e}")),
+		None => {
+			let mut input = String::new();
+			std::io::stdin().read_to_string(&mut input).map_err(|e| format!("Failed to read stdin: {e}"))?;
+			Ok(input)
+		}
+	}
+}
+
+fn decode(input: &str) -> Result<Memory, String> {
+	let bytes = offline::decode_base64(input)?;
+	match offline::header(&bytes) {
+		(version, Some(format)) => log::info!("Memory is schema version {version}, written as {format:?}"),
+		(version, None) => log::info!("Memory is schema version {version}, from before the format was recorded"),
+	}
+	Memory::decode(&bytes)
+}
+
+fn parse_format(text: &str) -> Result<Format, String> {
+	let (codec, compressed) = match text.strip_suffix("+deflate") {
+		Some(codec) => (codec, true),
+		None => (text, false),
+	};
+	let codec = match codec {
+		"rmp-named" => MemoryCodec::RmpNamed,
+		"rmp-compact" => MemoryCodec::RmpCompact,
+		"bitcode" => MemoryCodec::Bitcode,
+		"json" => MemoryCodec::Json,
+		_ => return Err(format!("Unknown format '{text}'")),
+	};
+	Ok(Format { codec, compressed })
 }
 
 fn setup_logging(verbosity: log::LevelFilter) {
@@ -13,7 +118,13 @@ fn setup_logging(verbosity: log::LevelFilter) {
                 message
             ))
         })
-        .chain(fern::Dispatch::new().chain(std::io::stdout()))
+        // Logs go to stderr so they don't end up mixed into the output.
+        .chain(fern::Dispatch::new().chain(std::io::stderr()))
+        .chain(fern::Output::call(|record| {
+            if record.level() == log::Level::Error {
+                ERRORS.fetch_add(1, Ordering::Relaxed);
+            }
+        }))
         .apply()
         .expect("expected setup_logging to only ever be called once per instance");
 }
@@ -1,89 +1,666 @@
+use std::{cell::{Cell, RefCell}, collections::HashMap, hash::Hash};
+
 use base64::Engine;
-use screeps::{Creep, ObjectId};
+use js_sys::JsString;
+use rmpv::Value;
+use screeps::{game, Creep, ObjectId, RoomName};
 use vecmap::VecMap;
 
+use crate::{graveyard::Graveyard, heatmap::Heatmap, roles::Role, room_planner::RoomLayout, JobFlag};
+
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct MemData {
-	pub creep_data: VecMap<ObjectId<Creep>, CreepData>,
+	/// Keyed by name, so a record can be made before the Creep has an ID.
+	/// Each entry is encoded on its own, so one that can't be read doesn't take the rest with it.
+	/// Entries are written sorted by name, so the same data always encodes the same way.
+	#[serde(serialize_with = "serialize_isolated", deserialize_with = "deserialize_isolated")]
+	pub creep_data: HashMap<String, CreepData>,
+	#[serde(default)]
+	pub heatmaps: VecMap<RoomName, Heatmap>,
+	#[serde(default)]
+	pub rooms: VecMap<RoomName, RoomData>,
+	/// Totals for Creeps whose data has been cleared out.
+	#[serde(default)]
+	pub graveyard: Graveyard,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct RoomData {
+	/// Layouts from before they were kept in their own segments. Moved there the next time the room is run.
+	#[serde(default, rename = "layout")]
+	pub legacy_layout: Option<RoomLayout>,
+	/// The controller level construction sites were last placed for.
+	pub planned_level: u8,
+	/// Names of the job providers turned off for this room.
+	#[serde(default)]
+	pub disabled_providers: Vec<String>,
 }
 
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct CreepData {
 	// pub current_task: Option<Job>,
 	pub current_task: Option<(crate::JobIdentifier, crate::dynamic_stuff::DynState)>,
+	/// How many jobs of each kind the Creep has finished over its life.
+	#[serde(default)]
+	pub jobs_finished: VecMap<JobFlag, u32>,
+	/// How many jobs of each kind the Creep has failed over its life.
+	#[serde(default)]
+	pub jobs_failed: VecMap<JobFlag, u32>,
+	/// The tick the Creep will die of old age, known once it's done spawning.
+	#[serde(default)]
+	pub expires: Option<u32>,
+	/// The last tick the Creep was alive.
+	#[serde(default)]
+	pub last_seen: u32,
+	/// Whether the Creep was sent to be recycled.
+	#[serde(default)]
+	pub seppuku: bool,
+	/// What the Creep was spawned as, if it was spawned by us.
+	#[serde(default)]
+	pub role: Option<Role>,
+	/// The kind of job the Creep was spawned for, until it's first given one.
+	#[serde(default)]
+	pub planned_job: Option<JobFlag>,
+	/// What the Creep has reserved in stores, replayed into the ledger every tick.
+	#[serde(default)]
+	pub reservations: Vec<crate::reservations::Reservation>,
+}
+
+/// Bumped whenever `MemData` changes in a way older memory can't be read as,
+/// with a migration added to bring older memory up to date.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Upgrades memory from the version at its index to the next one.
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// Every migration, in order. Memory at version `n` goes through every migration from index `n` on.
+const MIGRATIONS: &[Migration] = &[
+	from_unversioned,
+	isolate_creep_data,
+	key_creeps_by_name,
+];
+
+/// Memory from before the envelope was introduced is laid out the same as version 1.
+fn from_unversioned(data: Value) -> Result<Value, String> {
+	Ok(data)
+}
+
+/// Creep entries went from being part of the structure to blobs of their own.
+fn isolate_creep_data(mut data: Value) -> Result<Value, String> {
+	let Some(creep_data) = field_mut(&mut data, "creep_data") else {
+		return Ok(data);
+	};
+	let Value::Map(entries) = creep_data else {
+		return Err("Creep data isn't a map".to_string());
+	};
+
+	for (_, entry) in entries.iter_mut() {
+		let mut bytes = Vec::new();
+		rmpv::encode::write_value(&mut bytes, entry).map_err(|e| e.to_string())?;
+		*entry = Value::Binary(bytes);
+	}
+
+	Ok(data)
+}
+
+/// Creep entries went from being keyed by ID to being keyed by name.
+/// Creeps that can't be found anymore are dropped, since there's no way to know their name.
+/// Outside of the game they're kept under their ID instead, to be fixed by hand.
+fn key_creeps_by_name(mut data: Value) -> Result<Value, String> {
+	let Some(creep_data) = field_mut(&mut data, "creep_data") else {
+		return Ok(data);
+	};
+	let Value::Map(entries) = creep_data else {
+		return Err("Creep data isn't a map".to_string());
+	};
+
+	let before = entries.len();
+	*entries = std::mem::take(entries).into_iter().filter_map(|(id, entry)| {
+		let mut bytes = Vec::new();
+		rmpv::encode::write_value(&mut bytes, &id).ok()?;
+		let id = rmp_serde::from_slice::<ObjectId<Creep>>(&bytes).ok()?;
+		Some((Value::from(creep_key(id)?), entry))
+	}).collect();
+
+	if entries.len() < before {
+		log::warn!("Dropped {} Creep entries that couldn't be matched to a living Creep", before - entries.len());
+	}
+	Ok(data)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn creep_key(id: ObjectId<Creep>) -> Option<String> {
+	use screeps::SharedCreepProperties;
+	id.resolve().map(|creep| creep.name())
+}
+
+/// Outside of the game there's no telling which Creep an ID belonged to, so it's logged as an error
+/// and the ID is kept as the key.
+#[cfg(not(target_arch = "wasm32"))]
+fn creep_key(id: ObjectId<Creep>) -> Option<String> {
+	log::error!("Creep {id} can't be matched to a name outside of the game, keeping its entry under its ID");
+	Some(id.to_string())
+}
+
+fn field_mut<'a>(value: &'a mut Value, name: &str) -> Option<&'a mut Value> {
+	let Value::Map(fields) = value else { return None };
+	fields.iter_mut().find(|(key, _)| key.as_str() == Some(name)).map(|(_, value)| value)
+}
+
+/// How memory is turned into bytes before it's base64 encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MemoryCodec {
+	/// MessagePack with field names. Fields can be added or reordered without breaking older memory.
+	RmpNamed,
+	/// MessagePack with structs as arrays. Smaller, but migrations can't find fields by name, so it can't be migrated.
+	RmpCompact,
+	/// The smallest and fastest, but it can't be read without the layout it was written with, so it can't be migrated.
+	Bitcode,
+	/// Readable by hand, for debugging.
+	Json,
+}
+
+impl MemoryCodec {
+	pub const ALL: [MemoryCodec; 4] = [MemoryCodec::RmpNamed, MemoryCodec::RmpCompact, MemoryCodec::Bitcode, MemoryCodec::Json];
+
+	pub fn encode<T: serde::Serialize>(self, data: &T) -> Result<Vec<u8>, String> {
+		match self {
+			MemoryCodec::RmpNamed => rmp_serde::to_vec_named(data).map_err(|e| e.to_string()),
+			MemoryCodec::RmpCompact => rmp_serde::to_vec(data).map_err(|e| e.to_string()),
+			MemoryCodec::Bitcode => bitcode::serialize(data).map_err(|e| e.to_string()),
+			MemoryCodec::Json => serde_json::to_vec(data).map_err(|e| e.to_string()),
+		}
+	}
+
+	pub fn decode<T: serde::de::DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+		match self {
+			MemoryCodec::RmpNamed | MemoryCodec::RmpCompact => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+			MemoryCodec::Bitcode => bitcode::deserialize(bytes).map_err(|e| e.to_string()),
+			MemoryCodec::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+		}
+	}
+
+	/// Reads the bytes without knowing the layout, so they can be migrated.
+	fn decode_value(self, bytes: &[u8]) -> Result<Value, String> {
+		match self {
+			MemoryCodec::RmpNamed => rmpv::decode::read_value(&mut &bytes[..]).map_err(|e| e.to_string()),
+			MemoryCodec::RmpCompact => Err("Compact MessagePack memory can't be migrated".to_string()),
+			MemoryCodec::Bitcode => Err("Bitcode memory can't be migrated".to_string()),
+			MemoryCodec::Json => {
+				let json = serde_json::from_slice::<serde_json::Value>(bytes).map_err(|e| e.to_string())?;
+				let bytes = rmp_serde::to_vec_named(&json).map_err(|e| e.to_string())?;
+				rmpv::decode::read_value(&mut &bytes[..]).map_err(|e| e.to_string())
+			}
+		}
+	}
+}
+
+/// Everything needed to read memory back: the codec, and whether it was compressed after encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+	pub codec: MemoryCodec,
+	pub compressed: bool,
+}
+
+/// What memory is written as. Memory written in any other format is still read, and rewritten in this one.
+pub const FORMAT: Format = Format { codec: MemoryCodec::RmpNamed, compressed: false };
+/// How hard to compress, from 0 to 10.
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Wraps encoded memory with everything needed to read it back.
+/// The envelope itself is always named MessagePack, whatever the data inside is.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Envelope {
+	version: u32,
+	codec: MemoryCodec,
+	compressed: bool,
+	data: Blob,
+}
+
+/// Memory from before the codec was recorded, with the data inline as named MessagePack.
+#[derive(serde::Deserialize)]
+struct LegacyEnvelope<T> {
+	data: T,
+}
+
+/// Just the version and format, so they can be checked without decoding the rest.
+#[derive(Default, serde::Deserialize)]
+struct EnvelopeHeader {
+	version: u32,
+	#[serde(default)]
+	codec: Option<MemoryCodec>,
+	#[serde(default)]
+	compressed: bool,
+}
+
+/// The schema version and format memory was written with.
+/// Memory from before the format was recorded has none, and is always named MessagePack.
+pub fn header(bytes: &[u8]) -> (u32, Option<Format>) {
+	let header = rmp_serde::from_slice::<EnvelopeHeader>(bytes).unwrap_or_default();
+	(header.version, header.codec.map(|codec| Format { codec, compressed: header.compressed }))
+}
+
+/// Memory is written back at least this often, in ticks, even if nothing important changed.
+const PERSIST_RATE: u32 = 20;
+/// Raw memory can't grow past this.
+pub const MAX_MEMORY_SIZE: usize = 2 * 1024 * 1024;
+/// A warning is logged while memory is larger than this, well before it hits the limit.
+pub const WARN_MEMORY_SIZE: usize = 3 * MAX_MEMORY_SIZE / 4;
+/// How often the size warning is repeated, in ticks.
+const WARN_RATE: u32 = 1000;
+
+thread_local! {
+	/// Memory kept on the heap between ticks, so it only has to be decoded after a global reset.
+	static CACHE: RefCell<Option<MemData>> = const { RefCell::new(None) };
+	/// The generation of the raw memory last read or written. If it's changed, something else wrote to it.
+	static GENERATION: Cell<Option<u64>> = const { Cell::new(None) };
+	/// Whether something changed that shouldn't be lost to a global reset.
+	static DIRTY: Cell<bool> = const { Cell::new(false) };
+	static LAST_PERSIST: Cell<u32> = const { Cell::new(0) };
+	static LAST_SIZE_WARNING: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Takes memory for the tick, reusing the copy on the heap if raw memory hasn't changed since it was saved.
+/// Hand it back with `store` at the end of the tick.
+pub fn load() -> MemData {
+	let raw = screeps::raw_memory::get();
+	let generation = generation(&raw);
+	let unchanged = generation.is_some() && generation == GENERATION.get();
+	let cached = CACHE.take();
+
+	if unchanged && let Some(data) = cached {
+		return data;
+	}
+	if cached.is_some() {
+		log::info!("Raw memory was changed outside the bot, reloading it");
+	}
+
+	let data = read(&raw);
+	GENERATION.set(generation);
+	LAST_PERSIST.set(game::time());
+	data
 }
 
+/// The generation raw memory starts with, counting up with every write so changes are noticed
+/// without comparing the whole thing. Memory written by anything else has none, or a different one.
+fn generation(raw: &JsString) -> Option<u64> {
+	// Only the start is copied out of JS, it's never longer than the largest number and the separator.
+	let head = raw.slice(0, 21);
+	let end = head.index_of(&JsString::from(":"), 0);
+	if end < 0 {
+		return None;
+	}
+	head.slice(0, end as u32).as_string()?.parse().ok()
+}
+
+/// Puts memory back on the heap, writing it to raw memory if it's dirty or hasn't been written in a while.
+pub fn store(data: MemData) {
+	let now = game::time();
+	if DIRTY.get() || now.saturating_sub(LAST_PERSIST.get()) >= PERSIST_RATE {
+		let generation = GENERATION.get().map_or(0, |generation| generation.wrapping_add(1));
+		let raw = write(&data, Some(generation));
+		warn_if_large(raw.length() as usize);
+		screeps::raw_memory::set(&raw);
+		GENERATION.set(Some(generation));
+		LAST_PERSIST.set(now);
+		DIRTY.set(false);
+	}
+	CACHE.set(Some(data));
+}
+
+fn warn_if_large(size: usize) {
+	let now = game::time();
+	if size < WARN_MEMORY_SIZE || LAST_SIZE_WARNING.get().is_some_and(|last| now.saturating_sub(last) < WARN_RATE) {
+		return;
+	}
+
+	LAST_SIZE_WARNING.set(Some(now));
+	log::warn!("Memory is {size} bytes, {:.0}% of the limit. Check `cmd_memory_usage` for what's growing", size as f64 / MAX_MEMORY_SIZE as f64 * 100.0);
+}
+
+/// Makes sure memory is written back at the end of the tick.
+/// Anything that isn't marked only survives a global reset if it happens to be written within `PERSIST_RATE` ticks.
+pub fn mark_dirty() {
+	DIRTY.set(true);
+}
+
+/// Runs `f` on the heap copy of memory outside of the game loop, such as from a console command.
+/// Nothing is written back.
+pub fn read_memory<R>(f: impl FnOnce(&MemData) -> R) -> R {
+	let data = load();
+	let result = f(&data);
+	CACHE.set(Some(data));
+	result
+}
+
+/// Runs `f` on the heap copy of memory outside of the game loop, such as from a console command.
+/// The changes are written back right away if `f` succeeds. If it fails, nothing is written,
+/// so it should only fail before changing anything.
+pub fn with_memory_mut<R, E>(f: impl FnOnce(&mut MemData) -> Result<R, E>) -> Result<R, E> {
+	let mut data = load();
+	let result = f(&mut data);
+	if result.is_ok() {
+		mark_dirty();
+		store(data);
+	} else {
+		CACHE.set(Some(data));
+	}
+	result
+}
+
+/// Reads and decodes raw memory, skipping the heap.
 pub fn get_memory() -> MemData {
 	// js_sys::Reflect::delete_property(&js_sys::global(), &wasm_bindgen::JsValue::from_str("Memory")).unwrap();
 	// js_sys::Reflect::set(&js_sys::global(), &wasm_bindgen::JsValue::from_str("Memory"), &js_sys::Object::default().into()).unwrap();
 
-	let raw_memory = screeps::raw_memory::get().as_string().unwrap();
+	read(&screeps::raw_memory::get())
+}
+
+fn read(raw_memory: &JsString) -> MemData {
+	let raw_memory = raw_memory.as_string().unwrap();
 	if raw_memory.is_empty() {
 		return Default::default();
 	}
+	// Base64 never has a colon, so anything before one is the generation.
+	let encoded = raw_memory.split_once(':').map_or(raw_memory.as_str(), |(_, encoded)| encoded);
 
-	let data = match base64::prelude::BASE64_STANDARD_NO_PAD.decode(
-		screeps::raw_memory::get().as_string().unwrap()
-	) {
+	let data = match base64::prelude::BASE64_STANDARD_NO_PAD.decode(encoded) {
 		Ok(data) => data,
 		Err(err) => {
-			log::error!("Failed to decode memory: '{err}'. Dumping memory...");
-			log::error!("{}", screeps::raw_memory::get().as_string().unwrap());
+			log::error!("Failed to decode memory: '{err}'.");
+			backup(&raw_memory);
 			return Default::default();
 		}
 	};
 
-	// if let Ok(data) = bitcode::deserialize(&data) {
-	// 	data
-	// } else {
-	// 	log::error!("Memory not valid Bitcode! Dumping memory...");
-	// 	log::error!("{}", screeps::raw_memory::get().as_string().unwrap());
-	// 	MemData::default()
-	// }
+	match decode(&data) {
+		Ok(data) => data,
+		Err(err) => {
+			log::error!("Failed to load memory: '{err}'.");
+			backup(&raw_memory);
+			MemData::default()
+		}
+	}
+}
+
+/// Reads memory written with any schema version and format, migrating it if it's out of date.
+pub fn decode(bytes: &[u8]) -> Result<MemData, String> {
+	// Memory from before the envelope is just the data.
+	let header = rmp_serde::from_slice::<EnvelopeHeader>(bytes).unwrap_or_default();
+	if header.version > SCHEMA_VERSION {
+		return Err(format!("Memory is from schema version {}, but only up to {SCHEMA_VERSION} is known", header.version));
+	}
+
+	let Some(codec) = header.codec else {
+		return decode_legacy(bytes, header.version);
+	};
+
+	let envelope = rmp_serde::from_slice::<Envelope>(bytes).map_err(|e| e.to_string())?;
+	let payload = if envelope.compressed {
+		miniz_oxide::inflate::decompress_to_vec(&envelope.data.0).map_err(|e| format!("Failed to decompress memory: {e:?}"))?
+	} else {
+		envelope.data.0
+	};
 
-	if let Ok(data) = rmp_serde::from_slice(&data) {
-		data
+	if header.version == SCHEMA_VERSION {
+		return codec.decode(&payload);
+	}
+
+	let data = migrate(codec.decode_value(&payload)?, header.version)?;
+	from_value(&data)
+}
+
+/// Reads memory from before the codec was recorded in the envelope.
+fn decode_legacy(bytes: &[u8], version: u32) -> Result<MemData, String> {
+	if version == SCHEMA_VERSION {
+		return rmp_serde::from_slice::<LegacyEnvelope<MemData>>(bytes)
+			.map(|envelope| envelope.data)
+			.map_err(|e| e.to_string());
+	}
+
+	let value = rmpv::decode::read_value(&mut &bytes[..]).map_err(|e| e.to_string())?;
+	let data = if version == 0 {
+		value
 	} else {
-		log::error!("Memory not valid MessagePack! Dumping memory...");
-		log::error!("{}", screeps::raw_memory::get().as_string().unwrap());
-		MemData::default()
+		envelope_data(value).ok_or("Memory envelope has no data")?
+	};
+
+	from_value(&migrate(data, version)?)
+}
+
+fn from_value(data: &Value) -> Result<MemData, String> {
+	let mut bytes = Vec::new();
+	rmpv::encode::write_value(&mut bytes, data).map_err(|e| e.to_string())?;
+	rmp_serde::from_slice(&bytes).map_err(|e| format!("Migrated memory doesn't match the current layout: '{e}'"))
+}
+
+/// Runs every migration from `version` up to the current schema.
+pub fn migrate(mut data: Value, version: u32) -> Result<Value, String> {
+	for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+		data = migration(data).map_err(|e| format!("Migration from version {from} failed: '{e}'"))?;
+		log::info!("Migrated memory from schema version {from} to {}", from + 1);
 	}
+	Ok(data)
+}
 
-	// if let Ok(data) = serde_json::from_str(&raw_memory) {
-	// 	data
-	// } else {
-	// 	log::error!("Memory not valid JSON! Dumping memory...");
-	// 	log::error!("{}", raw_memory);
-	// 	MemData::default()
-	// }
+fn envelope_data(envelope: Value) -> Option<Value> {
+	let Value::Map(fields) = envelope else { return None };
+	fields.into_iter().find(|(key, _)| key.as_str() == Some("data")).map(|(_, value)| value)
 }
 
+/// Keeps a copy of memory that couldn't be read, so it can be recovered by hand.
+fn backup(raw_memory: &str) {
+	match crate::segments::write(crate::segments::MEMORY_BACKUP, raw_memory) {
+		Ok(()) => log::error!("Backed up unreadable memory to the {} segments", crate::segments::MEMORY_BACKUP.name),
+		Err(err) => {
+			log::error!("Failed to back up memory: '{err}'. Dumping memory...");
+			log::error!("{raw_memory}");
+		}
+	}
+}
+
+/// Encodes and writes memory straight to raw memory, skipping the heap.
+/// It's written without a generation, so the heap copy is replaced on the next load.
 pub fn set_memory(data: &MemData) {
-	// let data = match bitcode::serialize(data) {
-	// 	Ok(data) => data,
-	// 	Err(err) => {
-	// 		log::error!("Failed to serialize memory: '{err}'.");
-	// 		bitcode::serialize(&MemData::default()).unwrap()
-	// 	}
-	// };
-	
-	let data = match rmp_serde::to_vec_named(data) {
+	screeps::raw_memory::set(&write(data, None));
+}
+
+fn write(data: &MemData, generation: Option<u64>) -> JsString {
+	let data = match encode(data, FORMAT) {
 		Ok(data) => data,
 		Err(err) => {
 			log::error!("Failed to serialize memory: '{err}'.");
-			rmp_serde::to_vec(&MemData::default()).unwrap()
+			encode(&MemData::default(), FORMAT).unwrap()
+		}
+	};
+
+	let encoded = base64::prelude::BASE64_STANDARD_NO_PAD.encode(&data);
+	match generation {
+		Some(generation) => format!("{generation}:{encoded}").into(),
+		None => encoded.into(),
+	}
+}
+
+/// Encodes memory in `format`, wrapped in an envelope recording how to read it back.
+pub fn encode(data: &MemData, format: Format) -> Result<Vec<u8>, String> {
+	let payload = format.codec.encode(data)?;
+	let payload = if format.compressed {
+		miniz_oxide::deflate::compress_to_vec(&payload, COMPRESSION_LEVEL)
+	} else {
+		payload
+	};
+
+	let envelope = Envelope { version: SCHEMA_VERSION, codec: format.codec, compressed: format.compressed, data: Blob(payload) };
+	rmp_serde::to_vec_named(&envelope).map_err(|e| e.to_string())
+}
+
+/// Encoded bytes, stored as binary rather than a list of numbers.
+struct Blob(Vec<u8>);
+
+impl serde::Serialize for Blob {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_bytes(&self.0)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for Blob {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct BlobVisitor;
+
+		impl<'de> serde::de::Visitor<'de> for BlobVisitor {
+			type Value = Blob;
+
+			fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+				formatter.write_str("encoded bytes")
+			}
+
+			fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Blob, E> {
+				Ok(Blob(v.to_vec()))
+			}
+
+			fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Blob, E> {
+				Ok(Blob(v))
+			}
+
+			fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Blob, A::Error> {
+				let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+				while let Some(byte) = seq.next_element()? {
+					bytes.push(byte);
+				}
+				Ok(Blob(bytes))
+			}
 		}
+
+		deserializer.deserialize_bytes(BlobVisitor)
+	}
+}
+
+thread_local! {
+	/// Set while memory is turned into JSON for people to read, so entries aren't isolated.
+	static READABLE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Memory as JSON for people to read and edit, with every entry as it is rather than as a blob.
+/// Only for the console and offline tools, the Json codec keeps the same layout as the others.
+pub fn to_json(data: &MemData) -> Result<serde_json::Value, String> {
+	readable(|| serde_json::to_value(data).map_err(|e| e.to_string()))
+}
+
+/// Reads memory from JSON written by `to_json`.
+pub fn from_json(value: serde_json::Value) -> Result<MemData, String> {
+	readable(|| serde_json::from_value(value).map_err(|e| e.to_string()))
+}
+
+fn readable<R>(f: impl FnOnce() -> R) -> R {
+	READABLE.set(true);
+	let result = f();
+	READABLE.set(false);
+	result
+}
+
+/// Writes every value in the map as its own blob, unless it's being turned into JSON for people to read.
+/// Entries are sorted by key, since the map's own order changes between runs.
+fn serialize_isolated<S, K, V>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: serde::Serializer,
+	K: serde::Serialize + Ord,
+	V: serde::Serialize,
+{
+	use serde::ser::{Error, SerializeMap};
+
+	let mut sorted: Vec<_> = map.iter().collect();
+	sorted.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+	// People reading memory get the entries as they are.
+	if READABLE.get() {
+		return serializer.collect_map(sorted);
+	}
+
+	let mut entries = serializer.serialize_map(Some(sorted.len()))?;
+	for (key, value) in sorted {
+		let bytes = rmp_serde::to_vec_named(value).map_err(S::Error::custom)?;
+		entries.serialize_entry(key, &Blob(bytes))?;
+	}
+	entries.end()
+}
+
+/// Reads every blob in the map on its own. Values that can't be read are logged and reset.
+fn deserialize_isolated<'de, D, K, V>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+where
+	D: serde::Deserializer<'de>,
+	K: serde::Deserialize<'de> + Eq + Hash + std::fmt::Display,
+	V: serde::de::DeserializeOwned + Default,
+{
+	// Entries written by people aren't isolated, so mistakes in them are caught.
+	if READABLE.get() {
+		return serde::Deserialize::deserialize(deserializer);
+	}
+
+	let blobs: HashMap<K, Blob> = serde::Deserialize::deserialize(deserializer)?;
+
+	Ok(blobs.into_iter().map(|(key, Blob(bytes))| {
+		let value = rmp_serde::from_slice(&bytes).unwrap_or_else(|err| {
+			log::error!("Failed to decode entry for {key}: '{err}'. Resetting it. Raw bytes: {}", base64::prelude::BASE64_STANDARD_NO_PAD.encode(&bytes));
+			V::default()
+		});
+		(key, value)
+	}).collect())
+}
+
+/// How much raw memory one part of `MemData` takes up.
+pub struct SectionSize {
+	pub name: String,
+	pub entries: usize,
+	/// Encoded with the current format and base64, so it compares with the size of raw memory.
+	pub bytes: usize,
+}
+
+/// The encoded size of each top-level section, then of Creep data for each kind of state Creeps are in.
+/// Sections are encoded and compressed on their own, so they won't add up to the total exactly.
+pub fn sizes(data: &MemData) -> Result<Vec<SectionSize>, String> {
+	// Memory is written as unpadded base64.
+	let base64_len = |bytes: usize| (bytes * 4).div_ceil(3);
+	let packed_len = |bytes: &[u8]| if FORMAT.compressed {
+		miniz_oxide::deflate::compress_to_vec(bytes, COMPRESSION_LEVEL).len()
+	} else {
+		bytes.len()
+	};
+	let size = |name: &str, entries: usize, bytes: Result<Vec<u8>, String>| -> Result<SectionSize, String> {
+		Ok(SectionSize { name: name.to_string(), entries, bytes: base64_len(packed_len(&bytes?)) })
 	};
-	
-	let data = base64::prelude::BASE64_STANDARD_NO_PAD.encode(&data);
-
-	// let data = match serde_json::to_string(&data) {
-	// 	Ok(data) => data,
-	// 	Err(err) => {
-	// 		log::error!("Failed to serialize memory: '{err}'.");
-	// 		String::new()
-	// 	}
-	// };
-
-	screeps::raw_memory::set(&data.into());
+
+	// Each state's entries are compressed together, as they would be in the whole.
+	let mut by_state: Vec<(String, usize, Vec<u8>)> = Vec::new();
+	let mut creep_bytes = Vec::new();
+	for (name, creep) in &data.creep_data {
+		// Entries are stored as a name and a blob, so they're sized the same way.
+		let blob = Blob(rmp_serde::to_vec_named(creep).map_err(|e| e.to_string())?);
+		let bytes = FORMAT.codec.encode(&(name, blob))?;
+		creep_bytes.extend_from_slice(&bytes);
+
+		let state = creep.current_task.as_ref().map_or("None".to_string(), |(_, state)| format!("{:?}", state.flag));
+		let name = format!("creep_data: {state}");
+		match by_state.iter_mut().find(|(n, _, _)| *n == name) {
+			Some((_, entries, section)) => {
+				*entries += 1;
+				section.extend_from_slice(&bytes);
+			}
+			None => by_state.push((name, 1, bytes)),
+		}
+	}
+	let mut by_state: Vec<_> = by_state.into_iter()
+		.map(|(name, entries, bytes)| SectionSize { name, entries, bytes: base64_len(packed_len(&bytes)) })
+		.collect();
+	by_state.sort_unstable_by_key(|s| std::cmp::Reverse(s.bytes));
+
+	let mut sizes = vec![
+		// Already compressed, if memory is.
+		SectionSize { name: "total".to_string(), entries: 1, bytes: base64_len(encode(data, FORMAT)?.len()) },
+		SectionSize { name: "creep_data".to_string(), entries: data.creep_data.len(), bytes: base64_len(packed_len(&creep_bytes)) },
+		size("heatmaps", data.heatmaps.len(), FORMAT.codec.encode(&data.heatmaps))?,
+		size("rooms", data.rooms.len(), FORMAT.codec.encode(&data.rooms))?,
+		size("graveyard", 1, FORMAT.codec.encode(&data.graveyard))?,
+	];
+	sizes.extend(by_state);
+	Ok(sizes)
 }
@@ -0,0 +1,104 @@
+use screeps::{find, look, RoomXY, StructureContainer, StructureLink, StructureObject, StructureType, Terrain};
+
+use crate::{roles::{self, Role}, utils::prelude::*};
+
+/// Containers aren't placed for static mining before this controller level.
+const CONTAINER_MIN_LEVEL: u8 = 2;
+/// How often sources are checked for missing containers, in ticks.
+const CONTAINER_CHECK_RATE: u32 = 100;
+
+/// The container next to a source, if it's been built.
+pub fn container(source: &Source) -> Option<StructureContainer> {
+	source.pos().find_in_range(find::STRUCTURES, 1).into_iter().find_map(|s| match s {
+		StructureObject::StructureContainer(container) => Some(container),
+		_ => None,
+	})
+}
+
+/// A link next to a source, if it's been built.
+pub fn link(source: &Source) -> Option<StructureLink> {
+	source.pos().find_in_range(find::MY_STRUCTURES, 2).into_iter().find_map(|s| match s {
+		StructureObject::StructureLink(link) => Some(link),
+		_ => None,
+	})
+}
+
+/// A link a miner standing on `spot` can transfer into.
+pub fn link_at(spot: Position) -> Option<StructureLink> {
+	spot.find_in_range(find::MY_STRUCTURES, 1).into_iter().find_map(|s| match s {
+		StructureObject::StructureLink(link) => Some(link),
+		_ => None,
+	})
+}
+
+/// The tile a miner works a source from: on its container, or next to both it and its link.
+pub fn spot(source: &Source) -> Option<Position> {
+	if let Some(container) = container(source) {
+		return Some(container.pos());
+	}
+
+	let link = link(source)?;
+	let terrain = source.room()?.get_terrain();
+	screeps::Direction::iter()
+		.filter_map(|dir| source.pos().checked_add_direction(*dir).ok())
+		.find(|pos| pos.is_near_to(link.pos()) && terrain.get_xy(pos.xy()) != Terrain::Wall)
+}
+
+/// Whether a miner is already working the source, so other Creeps don't need to harvest it.
+pub fn is_mined(source: &Source) -> bool {
+	spot(source).is_some_and(|spot| {
+		spot.look_for(look::CREEPS).unwrap_or_default().iter()
+			.any(|c| c.my() && Role::of(c) == Role::Miner)
+	})
+}
+
+/// Whether the room can afford to spawn miners once its extensions are full.
+pub fn can_support_miners(room: &Room) -> bool {
+	let cost: u32 = roles::MINER_BODY.iter().map(|p| p.cost()).sum();
+	room.energy_capacity_available() >= cost
+}
+
+/// Places a container site next to every source that doesn't have one yet.
+/// The room's planned layout is used if it has one, so the container ends up where the planner expects.
+pub fn place_containers(room: &Room) {
+	// Without the layouts loaded, there's no telling whether the room has one.
+	if game::time() % CONTAINER_CHECK_RATE != 0 || !crate::layouts::loaded() {
+		return;
+	}
+
+	if !room.controller().is_some_and(|c| c.my() && c.level() >= CONTAINER_MIN_LEVEL) {
+		return;
+	}
+
+	let terrain = room.get_terrain();
+	let spawn = room.find(find::MY_SPAWNS, None).into_iter().next();
+
+	let layout = crate::layouts::get(room.name());
+	for source in room.find(find::SOURCES, None) {
+		let has_site = source.pos().find_in_range(find::MY_CONSTRUCTION_SITES, 1).iter()
+			.any(|s| s.structure_type() == StructureType::Container);
+		if has_site || container(&source).is_some() {
+			continue;
+		}
+
+		let planned = layout.as_ref().and_then(|layout| layout.structures.iter()
+			.find(|(ty, xy)| *ty == StructureType::Container && is_near(*xy, source.pos().xy()))
+			.map(|(_, xy)| *xy));
+		// Without a plan, the tile closest to the spawn keeps the walk short for haulers.
+		let fallback = || screeps::Direction::iter()
+			.filter_map(|dir| source.pos().checked_add_direction(*dir).ok())
+			.filter(|pos| terrain.get_xy(pos.xy()) != Terrain::Wall)
+			.min_by_key(|pos| spawn.as_ref().map(|s| s.pos().get_range_to(*pos)).unwrap_or(0))
+			.map(|pos| pos.xy());
+
+		let Some(xy) = planned.or_else(fallback) else { continue };
+		match room.create_construction_site(xy.x.u8(), xy.y.u8(), StructureType::Container, None) {
+			Ok(()) => log::info!("Placed container site for source at {}", source.pos()),
+			Err(e) => log::warn!("Failed to place container site for source at {}: {e:?}", source.pos()),
+		}
+	}
+}
+
+fn is_near(a: RoomXY, b: RoomXY) -> bool {
+	a.x.u8().abs_diff(b.x.u8()) <= 1 && a.y.u8().abs_diff(b.y.u8()) <= 1
+}
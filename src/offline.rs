@@ -0,0 +1,138 @@
+//! Working with memory outside of the game, for benchmarks and tools.
+
+use base64::Engine;
+use screeps::{ObjectId, Position, RoomName, RoomXY};
+
+use crate::{
+	dynamic_stuff::{DynState, StateFlag},
+	heatmap::Heatmap,
+	memory::{self, CreepData, MemData, RoomData},
+	state::harvester::StateHarvesting,
+	JobFlag,
+};
+
+pub use crate::memory::{header, Format, MemoryCodec, FORMAT, SCHEMA_VERSION};
+
+/// Reads memory as it's stored in raw memory. Surrounding whitespace, padding and the generation are ignored.
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+	let text = text.trim();
+	let text = text.split_once(':').map_or(text, |(_, encoded)| encoded);
+	base64::prelude::BASE64_STANDARD_NO_PAD.decode(text.trim_end_matches('=')).map_err(|e| e.to_string())
+}
+
+/// Writes memory the way it's stored in raw memory.
+pub fn encode_base64(bytes: &[u8]) -> String {
+	base64::prelude::BASE64_STANDARD_NO_PAD.encode(bytes)
+}
+
+#[derive(tabled::Tabled)]
+struct CreepRow {
+	#[tabled(rename = "Creep")]
+	name: String,
+	#[tabled(rename = "Role")]
+	role: String,
+	#[tabled(rename = "State")]
+	state: String,
+	#[tabled(rename = "Jobs finished")]
+	finished: u32,
+	#[tabled(rename = "Jobs failed")]
+	failed: u32,
+	#[tabled(rename = "Expires")]
+	expires: String,
+}
+
+#[derive(tabled::Tabled)]
+struct RoomRow {
+	#[tabled(rename = "Room")]
+	name: String,
+	#[tabled(rename = "Planned level")]
+	planned_level: u8,
+	#[tabled(rename = "Disabled providers")]
+	disabled: String,
+}
+
+/// Decoded memory.
+pub struct Memory(MemData);
+
+impl Memory {
+	/// Reads memory written with any schema version and format, migrating it if it's out of date.
+	pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+		memory::decode(bytes).map(Memory)
+	}
+
+	pub fn encode(&self, format: Format) -> Result<Vec<u8>, String> {
+		memory::encode(&self.0, format)
+	}
+
+	/// Reads memory from JSON, checking it against the current layout.
+	pub fn from_json(json: &str) -> Result<Self, String> {
+		let value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+		memory::from_json(value).map(Memory)
+	}
+
+	pub fn to_json(&self) -> Result<String, String> {
+		serde_json::to_string_pretty(&memory::to_json(&self.0)?).map_err(|e| e.to_string())
+	}
+
+	/// Tables of how large memory is, and what's in it for each Creep and room.
+	pub fn report(&self) -> Result<String, String> {
+		let sizes = crate::cmds::size_table(memory::sizes(&self.0)?);
+
+		let mut creeps: Vec<_> = self.0.creep_data.iter().map(|(name, data)| CreepRow {
+			name: name.clone(),
+			role: data.role.map_or("-".to_string(), |role| format!("{role:?}")),
+			state: data.current_task.as_ref().map_or("-".to_string(), |(_, state)| format!("{:?}", state.flag)),
+			finished: data.jobs_finished.values().sum(),
+			failed: data.jobs_failed.values().sum(),
+			expires: data.expires.map_or("-".to_string(), |tick| tick.to_string()),
+		}).collect();
+		creeps.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+		let rooms = self.0.rooms.iter().map(|(name, data)| RoomRow {
+			name: name.to_string(),
+			planned_level: data.planned_level,
+			disabled: data.disabled_providers.join(", "),
+		});
+
+		Ok(format!(
+			"Memory usage\n{sizes}\n\nCreeps\n{}\n\nRooms\n{}",
+			tabled::Table::new(creeps),
+			tabled::Table::new(rooms),
+		))
+	}
+
+	/// Memory shaped like a busy colony's, with random contents.
+	pub fn sample(rooms: u32, creeps: u32) -> Self {
+		let random_xy = || RoomXY::checked_new(fastrand::u8(1..49), fastrand::u8(1..49)).unwrap();
+		let room_names: Vec<_> = (0..rooms).map(|i| RoomName::new(&format!("W{i}N1")).unwrap()).collect();
+		let mut data = MemData::default();
+
+		for &room in &room_names {
+			let mut heatmap = Heatmap::default();
+			for _ in 0..5000 {
+				heatmap.add(random_xy());
+			}
+			data.heatmaps.insert(room, heatmap);
+
+			data.rooms.insert(room, RoomData { planned_level: 8, ..Default::default() });
+		}
+
+		for i in 0..creeps {
+			let task = if i % 2 == 0 {
+				crate::new_idle()
+			} else {
+				let xy = random_xy();
+				let room = room_names[i as usize % room_names.len()];
+				let source = ObjectId::from_packed(fastrand::u128(..));
+				let state = StateHarvesting::new(source, Some(Position::new(xy.x, xy.y, room)));
+				(
+					crate::JobIdentifier { job: JobFlag::Harvest, id: source.into_type() },
+					DynState::new(state, StateFlag::Harvesting),
+				)
+			};
+			data.creep_data.insert(format!("Creep{i}"), CreepData { current_task: Some(task), ..Default::default() });
+		}
+
+		Memory(data)
+	}
+}
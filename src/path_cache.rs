@@ -0,0 +1,180 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use screeps::{Position, RoomCoordinate, RoomName};
+
+use crate::{cost_matrix::{self, Overlay}, pathing::{find_path, CompactPath}, utils::prelude::*};
+
+/// Paths older than this many ticks are recalculated even if nothing changed.
+const MAX_AGE: u32 = 1500;
+/// Paths avoiding Creeps go stale as soon as the Creeps move.
+const MAX_AGE_AVOIDING_CREEPS: u32 = 5;
+/// How often expired entries are swept out of the cache, in ticks.
+const SWEEP_RATE: u32 = 100;
+/// Shared paths are looked up by which square of this many tiles across the origin is in.
+const SECTOR_SIZE: u8 = 5;
+
+thread_local! {
+	static CACHE: RefCell<PathCache> = RefCell::new(PathCache::default());
+}
+
+/// Everything that determines a path, and so identifies it in the cache.
+/// States store this instead of the path itself, and look the path up each time they need it.
+///
+/// Shared handles are looked up by the sector their origin is in rather than the exact tile,
+/// so Creeps starting near each other for the same destination and range use the same path.
+/// It's found from wherever the first of them was, and the rest join it from next to it.
+/// A cached path is replaced once it's older than `MAX_AGE`, or the cost matrix of any room
+/// it passes through is rebuilt. Creeps that can't join a shared path use an exact one instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct PathHandle {
+	pub origin: Position,
+	pub destination: Position,
+	pub range: u8,
+	/// Whether other Creeps are treated as obstacles.
+	pub avoid_creeps: bool,
+	/// Whether the path may be shared with Creeps starting elsewhere in the same sector.
+	#[serde(default)]
+	pub shared: bool,
+}
+
+impl PathHandle {
+	pub fn new(origin: impl HasPosition, destination: impl HasPosition, range: u8) -> Self {
+		Self { origin: origin.pos(), destination: destination.pos(), range, avoid_creeps: false, shared: true }
+	}
+
+	/// A path starting exactly at the origin, for when a shared one can't be joined.
+	pub fn exact(self) -> Self {
+		Self { shared: false, ..self }
+	}
+
+	/// Paths around other Creeps are only good for where the Creep is now, so they're never shared.
+	pub fn avoiding_creeps(self) -> Self {
+		Self { avoid_creeps: true, shared: false, ..self }
+	}
+
+	fn key(&self) -> PathKey {
+		let origin = if self.shared {
+			let snap = |n: u8| n - n % SECTOR_SIZE;
+			let xy = self.origin.xy();
+			Position::new(
+				RoomCoordinate::new(snap(xy.x.u8())).expect("Snapping should stay inside the room"),
+				RoomCoordinate::new(snap(xy.y.u8())).expect("Snapping should stay inside the room"),
+				self.origin.room_name(),
+			)
+		} else {
+			self.origin
+		};
+		PathKey { origin, destination: self.destination, range: self.range, avoid_creeps: self.avoid_creeps, shared: self.shared }
+	}
+}
+
+/// What paths are cached under. The origin is snapped to its sector for shared paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct PathKey {
+	origin: Position,
+	destination: Position,
+	range: u8,
+	avoid_creeps: bool,
+	shared: bool,
+}
+
+impl PathKey {
+	fn max_age(&self) -> u32 {
+		if self.avoid_creeps { MAX_AGE_AVOIDING_CREEPS } else { MAX_AGE }
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+	pub hits: u32,
+	pub misses: u32,
+	/// Entries dropped because they got too old or their rooms changed.
+	pub expired: u32,
+}
+
+impl CacheStats {
+	pub fn hit_rate(&self) -> f32 {
+		let total = self.hits + self.misses;
+		if total == 0 { 0.0 } else { self.hits as f32 / total as f32 }
+	}
+}
+
+#[derive(Default)]
+struct PathCache {
+	entries: HashMap<PathKey, CacheEntry>,
+	stats: CacheStats,
+	last_sweep: u32,
+}
+
+struct CacheEntry {
+	path: Rc<CompactPath>,
+	created: u32,
+	/// The cost matrix generation of every room the path passes through when it was found.
+	rooms: Vec<(RoomName, u32)>,
+}
+
+impl CacheEntry {
+	fn is_fresh(&self, key: &PathKey, now: u32) -> bool {
+		now.saturating_sub(self.created) < key.max_age()
+			&& self.rooms.iter().all(|&(room, generation)| cost_matrix::generation(room) == generation)
+	}
+}
+
+/// Gets the path for a handle, finding and caching it if it's missing or stale.
+pub fn get(handle: &PathHandle) -> Rc<CompactPath> {
+	let now = game::time();
+	let key = handle.key();
+
+	let cached = CACHE.with_borrow_mut(|cache| {
+		let entry = cache.entries.get(&key)?;
+		if entry.is_fresh(&key, now) {
+			cache.stats.hits += 1;
+			Some(entry.path.clone())
+		} else {
+			cache.entries.remove(&key);
+			cache.stats.expired += 1;
+			None
+		}
+	});
+
+	if let Some(path) = cached {
+		return path;
+	}
+
+	let overlays: &[Overlay] = if handle.avoid_creeps { &[Overlay::Creeps] } else { &[] };
+	let path = Rc::new(find_path(&handle.origin, &handle.destination, handle.range, overlays));
+	let rooms = path.rooms().into_iter().map(|room| (room, cost_matrix::generation(room))).collect();
+
+	CACHE.with_borrow_mut(|cache| {
+		cache.stats.misses += 1;
+		cache.entries.insert(key, CacheEntry { path: path.clone(), created: now, rooms });
+	});
+
+	path
+}
+
+/// Call once per tick. Periodically drops entries that have expired.
+pub fn tick() {
+	let now = game::time();
+	CACHE.with_borrow_mut(|cache| {
+		if now.saturating_sub(cache.last_sweep) < SWEEP_RATE {
+			return;
+		}
+		cache.last_sweep = now;
+
+		let before = cache.entries.len();
+		cache.entries.retain(|key, entry| entry.is_fresh(key, now));
+		let swept = before - cache.entries.len();
+
+		cache.stats.expired += swept as u32;
+		log::debug!("Swept {swept} expired paths, {} remain", cache.entries.len());
+	});
+}
+
+pub fn stats() -> CacheStats {
+	CACHE.with_borrow(|cache| cache.stats)
+}
+
+pub fn len() -> usize {
+	CACHE.with_borrow(|cache| cache.entries.len())
+}
@@ -0,0 +1,124 @@
+use screeps::{pathfinder::{self, MultiRoomCostResult, SearchOptions}, Direction, FindRouteOptions, Position, RoomName};
+
+use crate::{cost_matrix::{self, Overlay}, utils::prelude::*};
+
+/// Maximum number of operations a single search may use.
+/// The default of 2000 is too little for paths spanning several rooms.
+const MAX_OPS: u32 = 8000;
+
+/// A path stored as its starting position and the direction of every step.
+/// Each step serializes to a single byte, and as `Position` is world-space the
+/// path may freely cross room borders. Creeps stepping onto an exit tile are moved
+/// to the next room for free, which lands them on the next position of the path.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct CompactPath {
+	origin: Option<Position>,
+	steps: Vec<Direction>,
+}
+
+impl CompactPath {
+	pub fn from_positions(origin: Position, path: &[Position]) -> Self {
+		let mut steps = Vec::with_capacity(path.len());
+		let mut last = origin;
+		for &pos in path {
+			let Some(dir) = last.get_direction_to(pos) else {
+				break;
+			};
+			steps.push(dir);
+			last = pos;
+		}
+
+		Self { origin: Some(origin), steps }
+	}
+
+	/// Iterates over every position on the path, including the origin.
+	pub fn positions(&self) -> impl Iterator<Item = Position> + '_ {
+		let mut current = self.origin;
+		let mut steps = self.steps.iter();
+		std::iter::from_fn(move || {
+			let pos = current?;
+			current = steps.next().and_then(|dir| pos.checked_add_direction(*dir).ok());
+			Some(pos)
+		})
+	}
+
+	/// Every room the path passes through, in order.
+	pub fn rooms(&self) -> Vec<RoomName> {
+		let mut rooms: Vec<RoomName> = Vec::new();
+		for pos in self.positions() {
+			if rooms.last() != Some(&pos.room_name()) {
+				rooms.push(pos.room_name());
+			}
+		}
+		rooms
+	}
+
+	/// The last position on the path, if there is one.
+	pub fn end(&self) -> Option<Position> {
+		self.positions().last()
+	}
+
+	/// Returns the direction the Creep should step in if it's currently on the path.
+	/// Creeps next to the path step onto it, as far along as they can reach, so shared paths can be joined.
+	/// Returns None if the Creep is neither on nor next to the path, or at its end.
+	pub fn next_direction(&self, pos: Position) -> Option<Direction> {
+		if let Some(index) = self.positions().position(|p| p == pos) {
+			return self.steps.get(index).copied();
+		}
+		let join = self.positions().filter(|p| p.is_near_to(pos)).last()?;
+		pos.get_direction_to(join)
+	}
+}
+
+/// Finds a path from `start` to within `range` of `end`, crossing rooms if needed.
+/// A route between rooms is found first, and every room not on that route is
+/// excluded from the search.
+/// The overlays are applied to every room's costs.
+pub fn find_path(start: &impl HasPosition, end: &impl HasPosition, range: u8, overlays: &[Overlay]) -> CompactPath {
+	let start: Position = start.pos();
+	let end: Position = end.pos();
+
+	let route = route_rooms(start.room_name(), end.room_name());
+	let room_count = route.as_ref().map(|r| r.len()).unwrap_or(16).clamp(1, 64) as u8;
+
+	let options = SearchOptions::new(|room_name: RoomName| room_costs(room_name, route.as_deref(), overlays))
+		.plain_cost(2)
+		.swamp_cost(10)
+		.max_rooms(room_count)
+		.max_ops(MAX_OPS);
+
+	let result = pathfinder::search(start, end, range as u32, Some(options));
+	if result.incomplete() {
+		log::debug!("Incomplete path from {start} to {end}");
+	}
+
+	CompactPath::from_positions(start, &result.path())
+}
+
+/// The rooms a path between two rooms should go through, including both ends.
+/// None if no route could be found, in which case all rooms are allowed.
+fn route_rooms(from: RoomName, to: RoomName) -> Option<Vec<RoomName>> {
+	if from == to {
+		return Some(vec![from]);
+	}
+
+	match game::map::find_route(from, to, Some(FindRouteOptions::default())) {
+		Ok(steps) => Some(std::iter::once(from).chain(steps.into_iter().map(|step| step.room)).collect()),
+		Err(e) => {
+			log::warn!("Failed to find a route from {from} to {to}: {e:?}");
+			None
+		}
+	}
+}
+
+fn room_costs(room_name: RoomName, route: Option<&[RoomName]>, overlays: &[Overlay]) -> MultiRoomCostResult {
+	if let Some(route) = route && !route.contains(&room_name) {
+		return MultiRoomCostResult::Impassable;
+	}
+
+	match cost_matrix::get(room_name, overlays) {
+		Some(matrix) => MultiRoomCostResult::CostMatrix(matrix.into()),
+		// Never seen, so there's nothing to add to the terrain.
+		None => MultiRoomCostResult::Default,
+	}
+}
@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+
+use screeps::{ResourceType, StructureObject};
+
+use crate::{memory::MemData, utils::prelude::*};
+
+thread_local! {
+	static LEDGER: RefCell<Ledger> = RefCell::new(Ledger::default());
+}
+
+/// Which way a reserved amount is moving, from the store's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Flow {
+	Incoming,
+	Outgoing,
+}
+
+/// What a Creep holds on one store. Kept in the Creep's data between ticks.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Reservation {
+	target: StructureId,
+	resource: ResourceType,
+	flow: Flow,
+	amount: u32,
+	/// The last tick the Creep reserved it.
+	tick: u32,
+}
+
+/// Amounts Creeps are about to put into or take out of stores, by Creep.
+/// Every Creep's reservations are replayed at the start of the tick, so each Creep sees what
+/// the others hold no matter which runs first. Reservations a Creep doesn't renew are dropped at the end of the tick.
+#[derive(Default)]
+struct Ledger {
+	reservations: Vec<(String, Reservation)>,
+}
+
+impl Ledger {
+	fn total(&self, target: StructureId, resource: ResourceType, flow: Flow, except: Option<&str>) -> u32 {
+		self.reservations.iter()
+			.filter(|(_, r)| r.target == target && r.resource == resource && r.flow == flow)
+			.filter(|(creep, _)| except.is_none_or(|name| creep != name))
+			.map(|(_, r)| r.amount)
+			.sum()
+	}
+}
+
+/// Call once at the start of the tick, before jobs are generated or any state runs.
+pub fn load(memory: &MemData) {
+	LEDGER.with_borrow_mut(|ledger| {
+		ledger.reservations = memory.creep_data.iter()
+			.flat_map(|(name, data)| data.reservations.iter().map(|r| (name.clone(), r.clone())))
+			.collect();
+	});
+}
+
+/// Call once at the end of the tick. Keeps what each Creep reserved this tick in its data.
+pub fn save(memory: &mut MemData) {
+	let now = game::time();
+	LEDGER.with_borrow(|ledger| {
+		for (name, data) in memory.creep_data.iter_mut() {
+			let held: Vec<_> = ledger.reservations.iter()
+				.filter(|(creep, r)| creep == name && r.tick == now)
+				.map(|(_, r)| r.clone())
+				.collect();
+			if data.reservations != held {
+				data.reservations = held;
+				crate::memory::mark_dirty();
+			}
+		}
+	});
+}
+
+/// Reserves `amount` of `resource` moving into or out of `target` for the Creep.
+/// Replaces whatever the Creep had reserved on the target before.
+pub fn reserve(creep: &Creep, target: StructureId, resource: ResourceType, flow: Flow, amount: u32) {
+	LEDGER.with_borrow_mut(|ledger| {
+		let name = creep.name();
+		ledger.reservations.retain(|(creep, r)| !(r.target == target && *creep == name));
+		ledger.reservations.push((name, Reservation { target, resource, flow, amount, tick: game::time() }));
+	});
+}
+
+/// Drops whatever the Creep had reserved on `target`.
+pub fn release(creep: &Creep, target: StructureId) {
+	LEDGER.with_borrow_mut(|ledger| {
+		let name = creep.name();
+		ledger.reservations.retain(|(creep, r)| !(r.target == target && *creep == name));
+	});
+}
+
+/// The total reserved on `target` by every Creep.
+pub fn reserved(target: StructureId, resource: ResourceType, flow: Flow) -> u32 {
+	LEDGER.with_borrow(|ledger| ledger.total(target, resource, flow, None))
+}
+
+/// Space left in `target` once every other Creep has delivered what they reserved.
+pub fn free_capacity(creep: &Creep, target: &StructureObject, resource: ResourceType) -> u32 {
+	let Some(store) = target.as_has_store().map(|s| s.store()) else { return 0 };
+	let free = store.get_free_capacity(Some(resource)).max(0) as u32;
+	let id = target.as_structure().id();
+	free.saturating_sub(LEDGER.with_borrow(|ledger| ledger.total(id, resource, Flow::Incoming, Some(&creep.name()))))
+}
+
+/// What's left in `target` once every other Creep has taken what they reserved.
+pub fn available(creep: &Creep, target: &StructureObject, resource: ResourceType) -> u32 {
+	let Some(store) = target.as_has_store().map(|s| s.store()) else { return 0 };
+	let used = store.get_used_capacity(Some(resource));
+	let id = target.as_structure().id();
+	used.saturating_sub(LEDGER.with_borrow(|ledger| ledger.total(id, resource, Flow::Outgoing, Some(&creep.name()))))
+}
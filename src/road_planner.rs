@@ -0,0 +1,61 @@
+use screeps::{find, StructureType, Terrain};
+
+use crate::{heatmap::Heatmap, utils::prelude::*};
+
+/// How often roads are planned for each room, in ticks.
+const PLAN_RATE: u32 = 250;
+/// Tiles with at least this much heat get a road.
+const ROAD_THRESHOLD: u16 = 40;
+/// The most road sites placed per room in a single run.
+const MAX_SITES_PER_RUN: usize = 5;
+/// Don't place more roads while the room already has this many sites waiting.
+const MAX_PENDING_SITES: usize = 10;
+
+/// Places road construction sites on the busiest tiles of a room we own.
+/// The sites are then picked up by the usual construction jobs.
+pub fn plan_roads(room: &Room, heatmap: &Heatmap) {
+	if game::time() % PLAN_RATE != 0 {
+		return;
+	}
+
+	if !room.controller().is_some_and(|c| c.my()) {
+		return;
+	}
+
+	let pending = room.find(find::MY_CONSTRUCTION_SITES, None).len();
+	if pending >= MAX_PENDING_SITES {
+		return;
+	}
+
+	let terrain = room.get_terrain();
+	let mut placed = 0;
+
+	for (xy, heat) in heatmap.hot_tiles(ROAD_THRESHOLD) {
+		if placed >= MAX_SITES_PER_RUN.min(MAX_PENDING_SITES - pending) {
+			break;
+		}
+
+		if terrain.get_xy(xy) == Terrain::Wall {
+			continue;
+		}
+
+		let pos = Position::new(xy.x, xy.y, room.name());
+		let occupied = pos.look_for(screeps::look::STRUCTURES).unwrap_or_default().iter()
+			.any(|s| !matches!(s.structure_type(), StructureType::Container | StructureType::Rampart))
+			|| !pos.look_for(screeps::look::CONSTRUCTION_SITES).unwrap_or_default().is_empty();
+		if occupied {
+			continue;
+		}
+
+		match room.create_construction_site(xy.x.u8(), xy.y.u8(), StructureType::Road, None) {
+			Ok(()) => {
+				log::info!("Planned road at {pos} with heat {heat}");
+				placed += 1;
+			}
+			Err(e) => {
+				log::warn!("Failed to place road at {pos}: {e:?}");
+				break;
+			}
+		}
+	}
+}
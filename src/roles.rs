@@ -0,0 +1,67 @@
+use screeps::Part;
+
+use crate::{strategy::scaled_body, utils::prelude::*, JobFlag};
+
+use Part::*;
+
+/// Enough WORK parts to empty a source in an owned room before it regenerates.
+pub const MINER_BODY: &[Part] = &[Work, Work, Work, Work, Work, Carry, Move, Move, Move];
+const HAULER_TEMPLATE: &[Part] = &[Carry, Carry, Move];
+
+/// What a Creep is built for, worked out from its body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Role {
+	/// Harvests, builds and upgrades. Everything spawned before static mining.
+	Worker,
+	/// Sits next to a source and does nothing but harvest.
+	Miner,
+	/// Moves energy around and nothing else.
+	Hauler,
+}
+
+impl Role {
+	pub fn from_body(parts: impl IntoIterator<Item = Part>) -> Self {
+		let (mut work, mut carry) = (0, 0);
+		for part in parts {
+			match part {
+				Work => work += 1,
+				Carry => carry += 1,
+				_ => {}
+			}
+		}
+
+		if work >= 5 && carry <= 1 {
+			Role::Miner
+		} else if work == 0 && carry > 0 {
+			Role::Hauler
+		} else {
+			Role::Worker
+		}
+	}
+
+	pub fn of(creep: &Creep) -> Self {
+		Self::from_body(creep.body().iter().map(|p| p.part()))
+	}
+
+	/// Whether a Creep with this role should be given jobs of this kind.
+	pub fn can_take(self, job: JobFlag) -> bool {
+		match self {
+			Role::Miner => job == JobFlag::Mine,
+			Role::Hauler => job == JobFlag::Haul,
+			Role::Worker => job != JobFlag::Mine,
+		}
+	}
+}
+
+/// The body to spawn for a miner, if the room can afford one right now.
+pub fn miner_body(room: &Room) -> Option<Vec<Part>> {
+	let cost: u32 = MINER_BODY.iter().map(|p| p.cost()).sum();
+	(cost <= room.energy_available()).then(|| MINER_BODY.to_vec())
+}
+
+/// The biggest hauler the room can afford once its extensions are full.
+pub fn hauler_body(room: &Room) -> Option<Vec<Part>> {
+	let body = scaled_body(HAULER_TEMPLATE, room.energy_capacity_available());
+	let cost: u32 = body.iter().map(|p| p.cost()).sum();
+	(!body.is_empty() && cost <= room.energy_available()).then_some(body)
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashSet, VecDeque};
+
+use screeps::{find, ErrorCode, RoomXY, StructureType, Terrain};
+
+use crate::{blueprint::{self, Transform}, layouts, memory::RoomData, utils::prelude::*};
+
+use StructureType::*;
+
+/// The most construction sites placed for a room in a single tick.
+const MAX_SITES_PER_TICK: usize = 5;
+/// How often the layout is checked for missing structures even if the controller level didn't change.
+const REBUILD_RATE: u32 = 500;
+/// Tiles this close to the room's edge are never built on.
+const EDGE_MARGIN: u8 = 3;
+/// Roads aren't placed before this controller level, so early energy goes to the essentials.
+const ROAD_MIN_LEVEL: u8 = 2;
+/// The embedded blueprint placed at the anchor, holding spawns, storage, towers and the like.
+const CORE_BLUEPRINT: &str = "core";
+const LABS_BLUEPRINT: &str = "labs";
+
+/// Every structure planned for a room, in the order they should be built.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RoomLayout {
+	pub anchor: RoomXY,
+	pub structures: Vec<(StructureType, RoomXY)>,
+}
+
+/// Plans the room if it hasn't been yet, and places sites for whatever the
+/// controller level allows when it changes.
+pub fn run(room: &Room, data: &mut RoomData) {
+	let Some(controller) = room.controller().filter(|c| c.my()) else {
+		return;
+	};
+
+	// Layouts live in their own segments, which take a tick or two to load after a reset.
+	if !layouts::loaded() {
+		return;
+	}
+	if let Some(layout) = data.legacy_layout.take() {
+		layouts::set(room.name(), layout);
+		crate::memory::mark_dirty();
+	}
+
+	if !layouts::contains(room.name()) {
+		let cpu = game::cpu::get_used();
+		let layout = plan(room);
+		log::info!("Planned layout for room {} in {} CPU", room.name(), game::cpu::get_used() - cpu);
+		let Some(layout) = layout else {
+			log::warn!("Room {} has no space for a base", room.name());
+			return;
+		};
+		layouts::set(room.name(), layout);
+	}
+
+	let level = controller.level();
+	if data.planned_level == level && game::time() % REBUILD_RATE != 0 {
+		return;
+	}
+
+	let Some(layout) = layouts::get(room.name()) else { return };
+	// Only mark the level as done once nothing was left unplaced.
+	if place_sites(room, &layout, level) {
+		data.planned_level = level;
+		crate::memory::mark_dirty();
+	}
+}
+
+/// Places sites for the layout up to what `level` allows.
+/// Returns true if everything allowed has been placed.
+fn place_sites(room: &Room, layout: &RoomLayout, level: u8) -> bool {
+	let structures = room.find(find::STRUCTURES, None);
+	let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+
+	let built: HashSet<(StructureType, RoomXY)> = structures.iter().map(|s| (s.structure_type(), s.pos().xy()))
+		.chain(sites.iter().map(|s| (s.structure_type(), s.pos().xy())))
+		.collect();
+	let count = |ty: StructureType| built.iter().filter(|(t, _)| *t == ty).count() as u32;
+
+	let mut placed = 0;
+	let mut complete = true;
+	let mut extra: Vec<(StructureType, u32)> = Vec::new();
+
+	for &(ty, xy) in &layout.structures {
+		if built.contains(&(ty, xy)) {
+			continue;
+		}
+		if ty == Road && level < ROAD_MIN_LEVEL {
+			continue;
+		}
+
+		let already = count(ty) + extra.iter().find(|(t, _)| *t == ty).map(|(_, n)| *n).unwrap_or(0);
+		if already >= ty.controller_structures(level as u32) {
+			continue;
+		}
+
+		if placed >= MAX_SITES_PER_TICK {
+			complete = false;
+			break;
+		}
+
+		match room.create_construction_site(xy.x.u8(), xy.y.u8(), ty, None) {
+			Ok(()) => {
+				placed += 1;
+				match extra.iter_mut().find(|(t, _)| *t == ty) {
+					Some((_, n)) => *n += 1,
+					None => extra.push((ty, 1)),
+				}
+			}
+			// The tile or level won't allow it however long we wait, so there's no point trying again.
+			Err(e @ (ErrorCode::InvalidTarget | ErrorCode::InvalidArgs | ErrorCode::RclNotEnough | ErrorCode::NotOwner)) => {
+				log::debug!("Skipping planned {ty:?} at {xy:?} in room {}: {e:?}", room.name());
+			}
+			Err(e) => {
+				log::debug!("Failed to place planned {ty:?} at {xy:?} in room {}: {e:?}", room.name());
+				complete = false;
+			}
+		}
+	}
+
+	if placed > 0 {
+		log::info!("Placed {placed} planned construction sites in room {}", room.name());
+	}
+
+	complete
+}
+
+/// Working state of a plan in progress.
+struct Planner {
+	walls: [[bool; 50]; 50],
+	/// Tiles that already have something planned on them.
+	reserved: HashSet<RoomXY>,
+	/// Tiles the base itself must leave free, such as those Creeps harvest from.
+	keep_clear: HashSet<RoomXY>,
+	structures: Vec<(StructureType, RoomXY)>,
+}
+
+impl Planner {
+	/// Whether the tile can be part of the base.
+	fn buildable(&self, x: i16, y: i16) -> Option<RoomXY> {
+		let margin = EDGE_MARGIN as i16;
+		if x < margin || y < margin || x >= 50 - margin || y >= 50 - margin {
+			return None;
+		}
+		let xy = RoomXY::checked_new(x as u8, y as u8).ok()?;
+		(self.is_free(xy) && !self.keep_clear.contains(&xy)).then_some(xy)
+	}
+
+	/// Whether anything could be placed on the tile at all.
+	fn is_free(&self, xy: RoomXY) -> bool {
+		let (x, y) = (xy.x.u8(), xy.y.u8());
+		x > 0 && y > 0 && x < 49 && y < 49 && !self.walls[x as usize][y as usize] && !self.reserved.contains(&xy)
+	}
+
+	fn fits(&self, origin: RoomXY, stamp: &[(StructureType, i8, i8)]) -> bool {
+		stamp.iter().all(|&(_, dx, dy)| self.buildable(origin.x.u8() as i16 + dx as i16, origin.y.u8() as i16 + dy as i16).is_some())
+	}
+
+	fn stamp(&mut self, origin: RoomXY, stamp: &[(StructureType, i8, i8)]) {
+		for &(ty, dx, dy) in stamp {
+			let xy = offset(origin, dx, dy);
+			self.reserved.insert(xy);
+			self.structures.push((ty, xy));
+		}
+	}
+
+	fn add(&mut self, ty: StructureType, xy: RoomXY) {
+		self.reserved.insert(xy);
+		self.structures.push((ty, xy));
+	}
+
+	/// Every tile reachable from `origin` in order of distance, walking around walls.
+	fn flood(&self, origin: RoomXY) -> Vec<RoomXY> {
+		let mut seen = HashSet::from([origin]);
+		let mut queue = VecDeque::from([origin]);
+		let mut order = Vec::new();
+
+		while let Some(xy) = queue.pop_front() {
+			for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+				let (x, y) = (xy.x.u8() as i16 + dx, xy.y.u8() as i16 + dy);
+				if !(0..50).contains(&x) || !(0..50).contains(&y) || self.walls[x as usize][y as usize] {
+					continue;
+				}
+				let next = offset(xy, dx as i8, dy as i8);
+				if seen.insert(next) {
+					queue.push_back(next);
+					order.push(next);
+				}
+			}
+		}
+
+		order
+	}
+}
+
+fn offset(xy: RoomXY, dx: i8, dy: i8) -> RoomXY {
+	RoomXY::checked_new((xy.x.u8() as i16 + dx as i16) as u8, (xy.y.u8() as i16 + dy as i16) as u8)
+		.expect("Offset should stay inside the room")
+}
+
+/// Plans a full layout for the room, or None if the core doesn't fit anywhere.
+pub fn plan(room: &Room) -> Option<RoomLayout> {
+	let terrain = room.get_terrain();
+	let mut walls = [[false; 50]; 50];
+	for (x, column) in walls.iter_mut().enumerate() {
+		for (y, wall) in column.iter_mut().enumerate() {
+			let xy = RoomXY::checked_new(x as u8, y as u8).expect("Tile should be inside the room");
+			*wall = terrain.get_xy(xy) == Terrain::Wall;
+		}
+	}
+
+	let mut planner = Planner { walls, reserved: HashSet::new(), keep_clear: HashSet::new(), structures: Vec::new() };
+
+	// Structures already in the room, such as the first spawn or walls, are planned around.
+	// Roads and ramparts can share their tile, so they don't get in the way.
+	for structure in room.find(find::STRUCTURES, None) {
+		if !matches!(structure.structure_type(), Road | Rampart | Controller) {
+			planner.reserved.insert(structure.pos().xy());
+		}
+	}
+
+	let sources: Vec<RoomXY> = room.find(find::SOURCES, None).iter().map(|s| s.pos().xy()).collect();
+	let controller = room.controller().map(|c| c.pos().xy());
+	let mineral = room.find(find::MINERALS, None).first().map(|m| m.pos().xy());
+
+	// Keep the space around sources, the controller and the mineral free for the Creeps working them.
+	for xy in sources.iter().chain(controller.iter()).chain(mineral.iter()) {
+		for dx in -1..=1 {
+			for dy in -1..=1 {
+				if let Ok(xy) = RoomXY::checked_new((xy.x.u8() as i16 + dx) as u8, (xy.y.u8() as i16 + dy) as u8) {
+					planner.keep_clear.insert(xy);
+				}
+			}
+		}
+	}
+
+	let core = blueprint::get(CORE_BLUEPRINT).expect("Core blueprint should be embedded");
+	let cores: Vec<_> = Transform::all().map(|t| core.offsets(t)).collect();
+
+	// The anchor is the spot closest to everything that needs to be visited often.
+	let score = |xy: &RoomXY| -> u32 {
+		let range = |other: &RoomXY| xy.x.u8().abs_diff(other.x.u8()).max(xy.y.u8().abs_diff(other.y.u8())) as u32;
+		sources.iter().map(range).sum::<u32>() + controller.as_ref().map(range).unwrap_or(0)
+	};
+	let mut candidates: Vec<RoomXY> = (0..50u8)
+		.flat_map(|x| (0..50u8).map(move |y| (x, y)))
+		.filter_map(|(x, y)| RoomXY::checked_new(x, y).ok())
+		.collect();
+	candidates.sort_by_cached_key(score);
+
+	// Turning the core lets it fit rooms where it wouldn't otherwise.
+	let (anchor, core) = candidates.into_iter()
+		.find_map(|xy| cores.iter().find(|core| planner.fits(xy, core)).map(|core| (xy, core)))?;
+	planner.stamp(anchor, core);
+
+	// Containers and links next to each source, and a link by the controller.
+	for source in &sources {
+		let container = planner.flood(anchor).into_iter().find(|xy| is_near(*xy, *source) && planner.is_free(*xy));
+		if let Some(container) = container {
+			planner.add(Container, container);
+			let link = planner.flood(anchor).into_iter()
+				.find(|xy| is_near(*xy, container) && planner.buildable(xy.x.u8() as i16, xy.y.u8() as i16).is_some());
+			if let Some(link) = link {
+				planner.add(Link, link);
+			}
+		}
+	}
+	if let Some(controller) = controller {
+		let link = planner.flood(anchor).into_iter()
+			.find(|xy| in_range(*xy, controller, 2) && planner.buildable(xy.x.u8() as i16, xy.y.u8() as i16).is_some());
+		if let Some(link) = link {
+			planner.add(Link, link);
+		}
+	}
+
+	let labs = blueprint::get(LABS_BLUEPRINT).expect("Labs blueprint should be embedded");
+	let labs: Vec<_> = Transform::all().map(|t| labs.offsets(t)).collect();
+	let placement = planner.flood(anchor).into_iter()
+		.find_map(|xy| labs.iter().find(|labs| planner.fits(xy, labs)).map(|labs| (xy, labs)));
+	if let Some((origin, labs)) = placement {
+		planner.stamp(origin, labs);
+	}
+
+	// Extensions on a checkerboard around the core, so every one has a road next to it.
+	let parity = (anchor.x.u8() + anchor.y.u8()) % 2;
+	let mut extensions = 0;
+	for xy in planner.flood(anchor) {
+		if extensions >= Extension.controller_structures(8) {
+			break;
+		}
+		if planner.buildable(xy.x.u8() as i16, xy.y.u8() as i16).is_none() {
+			continue;
+		}
+		if (xy.x.u8() + xy.y.u8()) % 2 == parity {
+			planner.add(Extension, xy);
+			extensions += 1;
+		} else {
+			planner.add(Road, xy);
+		}
+	}
+
+	// Most important first, so they're built first when sites are limited.
+	let priority = |ty: &StructureType| match ty {
+		Spawn => 0,
+		Extension => 1,
+		Tower => 2,
+		Storage => 3,
+		Container => 4,
+		Link => 5,
+		Terminal => 6,
+		Lab => 7,
+		_ => 8,
+	};
+	planner.structures.sort_by_key(|(ty, _)| priority(ty));
+
+	Some(RoomLayout { anchor, structures: planner.structures })
+}
+
+fn in_range(a: RoomXY, b: RoomXY, range: u8) -> bool {
+	a.x.u8().abs_diff(b.x.u8()) <= range && a.y.u8().abs_diff(b.y.u8()) <= range
+}
+
+fn is_near(a: RoomXY, b: RoomXY) -> bool {
+	in_range(a, b, 1)
+}
@@ -0,0 +1,235 @@
+use std::{cell::RefCell, collections::{BTreeMap, HashMap}};
+
+use base64::Engine;
+
+use crate::utils::prelude::*;
+
+/// The game won't make more segments than this active at once.
+const MAX_ACTIVE: usize = 10;
+/// The game won't save more segments than this in one tick.
+const MAX_WRITES: usize = 10;
+/// The most a single segment can hold.
+const MAX_SEGMENT_SIZE: usize = 100 * 1024;
+/// Segments are numbered from 0 up to this.
+const SEGMENT_COUNT: usize = 100;
+
+/// A range of segments owned by one subsystem.
+/// Payloads too large for one segment are split across the rest of the range in order.
+#[derive(Clone, Copy, Debug)]
+pub struct Claim {
+	pub name: &'static str,
+	first: u8,
+	count: u8,
+}
+
+impl Claim {
+	fn ids(self) -> impl Iterator<Item = u8> {
+		self.first..self.first + self.count
+	}
+
+	/// The most the claim can hold.
+	pub fn capacity(self) -> usize {
+		self.count as usize * MAX_SEGMENT_SIZE
+	}
+}
+
+/// Cost matrices, so they survive global resets without every room being rebuilt at once.
+pub const COST_MATRICES: Claim = Claim { name: "cost_matrices", first: 1, count: 3 };
+/// Planned room layouts, which are large and only change when a room is planned.
+pub const LAYOUTS: Claim = Claim { name: "layouts", first: 4, count: 2 };
+/// A snapshot of the colony as JSON, for graphs outside the game.
+pub const STATS: Claim = Claim { name: "stats", first: 6, count: 1 };
+/// Recent warnings and errors, so they can be read after they've scrolled out of the console.
+pub const LOGS: Claim = Claim { name: "logs", first: 7, count: 2 };
+/// Memory that couldn't be read, kept so it can be recovered by hand. Large enough for all of it.
+pub const MEMORY_BACKUP: Claim = Claim { name: "memory_backup", first: 80, count: 20 };
+
+/// Every claim. Segments are only handed out here, so they stay put across resets.
+const CLAIMS: &[Claim] = &[COST_MATRICES, LAYOUTS, STATS, LOGS, MEMORY_BACKUP];
+const _: () = check_claims(CLAIMS);
+/// Claims read soon after every reset, requested on the first tick so they're ready a tick sooner.
+const PREFETCH: &[Claim] = &[COST_MATRICES, LAYOUTS, LOGS];
+
+/// Fails to compile if any claims overlap or run past the last segment.
+const fn check_claims(claims: &[Claim]) {
+	let mut i = 0;
+	while i < claims.len() {
+		let claim = claims[i];
+		assert!(claim.count > 0 && claim.first as usize + claim.count as usize <= SEGMENT_COUNT, "Claim runs past the last segment");
+
+		let mut j = i + 1;
+		while j < claims.len() {
+			let other = claims[j];
+			assert!(claim.first + claim.count <= other.first || other.first + other.count <= claim.first, "Claims overlap");
+			j += 1;
+		}
+		i += 1;
+	}
+}
+
+thread_local! {
+	static SEGMENTS: RefCell<Segments> = RefCell::new(Segments::default());
+}
+
+#[derive(Default)]
+struct Segments {
+	/// Whether a tick has run since the last reset.
+	started: bool,
+	/// Contents of every segment loaded or written since the last reset.
+	loaded: HashMap<u8, String>,
+	/// Segments someone is waiting on, in the order they were asked for.
+	wanted: Vec<u8>,
+	/// Segments activated last tick, which the game has made available this tick.
+	active: Vec<u8>,
+	/// Contents waiting to be saved, held back when too many segments were written in one tick.
+	writes: BTreeMap<u8, String>,
+}
+
+/// Call once per tick, before anything reads or writes segments.
+/// Picks up segments requested last tick, saves pending writes and requests the next batch.
+pub fn tick() {
+	SEGMENTS.with_borrow_mut(|segments| {
+		let raw = screeps::raw_memory::segments();
+		for id in std::mem::take(&mut segments.active) {
+			// Segments that have never been written come back empty.
+			segments.loaded.insert(id, raw.get(id).unwrap_or_default());
+		}
+		segments.wanted.retain(|id| !segments.loaded.contains_key(id));
+
+		if !segments.started {
+			segments.started = true;
+			for claim in PREFETCH {
+				segments.want(*claim);
+			}
+		}
+
+		let ready: Vec<_> = segments.writes.keys().take(MAX_WRITES).copied().collect();
+		for id in ready {
+			if let Some(data) = segments.writes.remove(&id) {
+				raw.set(id, data);
+			}
+		}
+
+		segments.activate();
+	});
+}
+
+impl Segments {
+	/// Asks for every segment in the claim that hasn't been loaded. Returns whether any were missing.
+	fn want(&mut self, claim: Claim) -> bool {
+		let mut missing = false;
+		for id in claim.ids().filter(|id| !self.loaded.contains_key(id)) {
+			missing = true;
+			if !self.wanted.contains(&id) {
+				self.wanted.push(id);
+			}
+		}
+		missing
+	}
+
+	/// Has the game make the first wanted segments available next tick.
+	/// Each call replaces the last one's, so segments asked for later in the tick aren't held back a tick.
+	fn activate(&mut self) {
+		self.active = self.wanted.iter().take(MAX_ACTIVE).copied().collect();
+		screeps::raw_memory::set_active_segments(&self.active);
+	}
+}
+
+/// Requests the claim's segments ahead of time, so a later `read` finds them loaded.
+pub fn prefetch(claim: Claim) {
+	SEGMENTS.with_borrow_mut(|segments| {
+		if segments.want(claim) {
+			segments.activate();
+		}
+	});
+}
+
+/// The claim's contents, if every segment in it has been loaded.
+/// Otherwise the missing segments are requested and None is returned, so try again on a later tick.
+pub fn read(claim: Claim) -> Option<String> {
+	SEGMENTS.with_borrow_mut(|segments| {
+		if segments.want(claim) {
+			segments.activate();
+			return None;
+		}
+
+		Some(claim.ids().filter_map(|id| segments.loaded.get(&id)).map(String::as_str).collect())
+	})
+}
+
+/// Replaces the claim's contents, splitting them across its segments.
+/// They're saved over the next few ticks, but reading them back gives the new contents right away.
+pub fn write(claim: Claim, data: &str) -> Result<(), String> {
+	if data.len() > claim.capacity() {
+		return Err(format!("{} bytes is too large for the {} segments, which hold {}", data.len(), claim.name, claim.capacity()));
+	}
+
+	let mut chunks = Vec::new();
+	let mut rest = data;
+	for _ in claim.ids() {
+		let mut end = rest.len().min(MAX_SEGMENT_SIZE);
+		while !rest.is_char_boundary(end) {
+			end -= 1;
+		}
+		let (chunk, remaining) = rest.split_at(end);
+		chunks.push(chunk);
+		rest = remaining;
+	}
+	if !rest.is_empty() {
+		return Err(format!("Couldn't split the data evenly enough to fit in the {} segments", claim.name));
+	}
+
+	SEGMENTS.with_borrow_mut(|segments| {
+		for (id, chunk) in claim.ids().zip(chunks) {
+			// Segments already known to hold the same thing don't need to be saved again.
+			if segments.loaded.get(&id).is_some_and(|loaded| loaded == chunk) {
+				continue;
+			}
+			segments.loaded.insert(id, chunk.to_string());
+			segments.writes.insert(id, chunk.to_string());
+		}
+	});
+
+	Ok(())
+}
+
+/// The claim's contents decoded, once every segment in it has loaded. A claim that's never been written is the default.
+pub fn read_decoded<T: serde::de::DeserializeOwned + Default>(claim: Claim) -> Option<Result<T, String>> {
+	let raw = read(claim)?;
+	if raw.is_empty() {
+		return Some(Ok(T::default()));
+	}
+
+	Some(try {
+		let bytes = base64::prelude::BASE64_STANDARD_NO_PAD.decode(raw).map_err(|e| e.to_string())?;
+		rmp_serde::from_slice(&bytes).map_err(|e| e.to_string())?
+	})
+}
+
+/// Encodes `entries` as a map and writes them to the claim.
+/// If they don't all fit, entries are left out from the end until they do. Returns how many were left out.
+pub fn write_map<K: serde::Serialize, V: serde::Serialize>(claim: Claim, entries: &[(K, V)]) -> Result<usize, String> {
+	let mut keep = entries.len();
+	loop {
+		let bytes = rmp_serde::to_vec(&MapEntries(&entries[..keep])).map_err(|e| e.to_string())?;
+		let encoded = base64::prelude::BASE64_STANDARD_NO_PAD.encode(bytes);
+		if encoded.len() <= claim.capacity() {
+			write(claim, &encoded)?;
+			return Ok(entries.len() - keep);
+		}
+		if keep == 0 {
+			return Err(format!("The {} segments can't even hold an empty map", claim.name));
+		}
+		// Entries are usually about the same size, so this skips close to what fits.
+		keep = (keep * claim.capacity() / encoded.len()).min(keep - 1);
+	}
+}
+
+/// Serializes a list of pairs as a map, so it reads back as one.
+struct MapEntries<'a, K, V>(&'a [(K, V)]);
+
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for MapEntries<'_, K, V> {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+	}
+}
@@ -0,0 +1,106 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use screeps::{look, Direction, Position, StructureType, Terrain};
+
+use crate::{cost_matrix, utils::prelude::*};
+
+/// Claims not held for this many ticks are given up, such as when their Creep died.
+const CLAIM_TIMEOUT: u32 = 3;
+/// Upgraders can work the controller from this far away.
+const CONTROLLER_RANGE: u32 = 3;
+/// Standing on a road is allowed, but only if nothing else is free.
+const ROAD_PENALTY: u32 = 10;
+
+thread_local! {
+	/// The Creep holding each tile, and the last tick they held it.
+	static CLAIMS: RefCell<HashMap<Position, (String, u32)>> = RefCell::new(HashMap::new());
+}
+
+fn is_free(claims: &HashMap<Position, (String, u32)>, pos: Position, name: &str, now: u32) -> bool {
+	claims.get(&pos).is_none_or(|(holder, held)| holder == name || now.saturating_sub(*held) > CLAIM_TIMEOUT)
+}
+
+/// Claims the best free tile out of `candidates` for the Creep, giving up any other tile it held.
+/// Tiles Creeps can't stand on are skipped.
+fn claim(creep: &Creep, candidates: impl Iterator<Item = Position>) -> Option<Position> {
+	let pos = creep.pos();
+	let terrain = game::map::get_room_terrain(pos.room_name());
+	let costs = cost_matrix::get(pos.room_name(), &[]);
+	let name = creep.name();
+	let now = game::time();
+
+	CLAIMS.with_borrow_mut(|claims| {
+		let spot = candidates
+			.filter(|p| p.room_name() == pos.room_name())
+			.filter(|p| terrain.as_ref().is_none_or(|t| t.get_xy(p.xy()) != Terrain::Wall))
+			.filter(|p| costs.as_ref().is_none_or(|c| c.get(p.xy()) != u8::MAX))
+			.filter(|p| is_free(claims, *p, &name, now))
+			.min_by_key(|p| {
+				let on_road = p.look_for(look::STRUCTURES).unwrap_or_default().iter().any(|s| s.structure_type() == StructureType::Road);
+				pos.get_range_to(*p) + if on_road { ROAD_PENALTY } else { 0 }
+			})?;
+
+		claims.retain(|_, (holder, _)| *holder != name);
+		claims.insert(spot, (name, now));
+		Some(spot)
+	})
+}
+
+/// Claims a tile next to the source to harvest from.
+/// The source's mining spot is left for its miner.
+pub fn claim_near_source(creep: &Creep, source: &Source) -> Option<Position> {
+	let pos = source.pos();
+	let mining = crate::mining::spot(source);
+	claim(creep, Direction::iter()
+		.filter_map(|dir| pos.checked_add_direction(*dir).ok())
+		.filter(|p| Some(*p) != mining))
+}
+
+/// Claims a tile in range of the controller to upgrade from.
+/// Tiles further away are as good as closer ones, so Creeps don't crowd the controller.
+pub fn claim_near_controller(creep: &Creep, controller: &StructureController) -> Option<Position> {
+	let pos = controller.pos();
+	let range = CONTROLLER_RANGE as i32;
+	let candidates = (-range..=range)
+		.flat_map(|dx| (-range..=range).map(move |dy| (dx, dy)))
+		.filter(|offset| *offset != (0, 0))
+		.filter_map(move |offset| pos.checked_add(offset).ok());
+	claim(creep, candidates)
+}
+
+/// Keeps the Creep's claim on `spot` for another tick.
+/// Returns false if someone else has taken it in the meantime.
+pub fn hold(creep: &Creep, spot: Position) -> bool {
+	let name = creep.name();
+	let now = game::time();
+	CLAIMS.with_borrow_mut(|claims| {
+		if !is_free(claims, spot, &name, now) {
+			return false;
+		}
+		claims.insert(spot, (name, now));
+		true
+	})
+}
+
+/// Claims `spot` for the Creep even if someone else holds it, giving up any other tile it held.
+/// Whoever held it finds out the next time they try to hold it.
+pub fn take(creep: &Creep, spot: Position) {
+	let name = creep.name();
+	let now = game::time();
+	CLAIMS.with_borrow_mut(|claims| {
+		claims.retain(|pos, (holder, _)| *holder != name || *pos == spot);
+		claims.insert(spot, (name, now));
+	});
+}
+
+/// Gives up whatever tile the Creep held.
+pub fn release(creep: &Creep) {
+	let name = creep.name();
+	CLAIMS.with_borrow_mut(|claims| claims.retain(|_, (holder, _)| *holder != name));
+}
+
+/// Call once per tick. Drops claims that haven't been held for a while.
+pub fn tick() {
+	let now = game::time();
+	CLAIMS.with_borrow_mut(|claims| claims.retain(|_, (_, held)| now.saturating_sub(*held) <= CLAIM_TIMEOUT));
+}
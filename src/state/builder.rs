@@ -3,7 +3,7 @@ use screeps::{ConstructionSite, ErrorCode, ResourceType};
 use general_states::{StateMove, StateWithdraw};
 use move_to::{MoveToError, StateMoveTo, StateMoveToExt};
 use reoccurring::{CheckFunc, StateReoccurring, StateReoccurringExt};
-use crate::reoccurring_check;
+use crate::{logistics::{self, Claim}, reoccurring_check, reservations::Flow};
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct StateBuilding {
@@ -70,6 +70,8 @@ impl State for StateBuilding {
 			}
 		}
 
+		crate::traffic::request_stay(creep, target.pos(), 3);
+
 		Finished(verge.into())
 	}
 }
@@ -78,6 +80,9 @@ impl State for StateBuilding {
 pub struct StateBuilderJob {
 	target: ObjectId<ConstructionSite>,
 	container: StructureId,
+	/// Energy claimed in the logistics network for the trip to the container.
+	#[serde(default)]
+	pickup: Option<Claim>,
 
 	current_state: PotentialState,
 }
@@ -87,9 +92,12 @@ impl StateBuilderJob {
 		let site = target.resolve().unwrap();
 		
 		let required = site.progress_total() - site.progress();
+		let mut pickup = None;
 		let current_state = if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0
 			&& creep.store().get_used_capacity(Some(ResourceType::Energy)) < required
 		{
+			pickup = logistics::energy_source(creep);
+			let container = pickup.map_or(container, |claim| claim.target);
 			let dest = container.resolve().unwrap();
 			PotentialState::Collecting(StateWithdraw::new(container, ResourceType::Energy, None)
 				.move_to(StateMove::new_from_ends(creep, dest, 1)))
@@ -99,7 +107,7 @@ impl StateBuilderJob {
 				.move_to(StateMove::new_from_ends(creep, site, 3)))
 		};
 
-		Self { target, container, current_state }
+		Self { target, container, pickup, current_state }
 	}
 }
 
@@ -134,6 +142,20 @@ impl State for StateBuilderJob {
 	type Return = BuildReturn;
 
 	fn run(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		if let Some(claim) = &self.pickup {
+			claim.hold(creep, ResourceType::Energy, Flow::Outgoing);
+		}
+
+		let result = self.step(creep, data);
+		if !matches!(result, Working) && let Some(claim) = self.pickup.take() {
+			claim.release(creep);
+		}
+		result
+	}
+}
+
+impl StateBuilderJob {
+	fn step(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<BuildReturn, StateBuilderJobError> {
 		match self.current_state {
 			PotentialState::Building(ref mut state) => {
 				match state.run(creep, data) {
@@ -154,6 +176,9 @@ impl State for StateBuilderJob {
 				match state.run(creep, data) {
 					Working => Working,
 					Finished(_) => {
+						if let Some(claim) = self.pickup.take() {
+							claim.release(creep);
+						}
 						let site = self.target.resolve().ok_or(StateBuilderJobError::TargetNotReal)?;
 						self.current_state = PotentialState::Building(StateBuilding::new(self.target)
 							.reoccurring_cond(ReoccurringBuildCheck::default())
@@ -1,12 +1,14 @@
 use super::*;
-use screeps::{Creep, ErrorCode, ObjectId, Position, ResourceType, Structure, StructureObject };
-use wasm_bindgen::JsValue;
+use screeps::{Creep, ErrorCode, ObjectId, Part, Position, ResourceType, Structure, StructureObject };
+use crate::{path_cache::{self, PathHandle}, reservations::{self, Flow}, traffic};
 
 #[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
 pub enum GenericStateError {
 	OutOfRange,
 	TargetNotReal,
 	NoParts,
+	/// Another Creep holds the tile the state was going to stand on.
+	SpotTaken,
 	Unknown,
 }
 
@@ -51,6 +53,32 @@ impl State for StateTransfer {
 	type Return = TransferReturn;
 
 	fn run(&mut self, creep: &Creep, _data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		let result = self.attempt(creep);
+		if !matches!(result, Working) {
+			reservations::release(creep, self.target);
+		}
+		result
+	}
+
+	fn reserve(&mut self, creep: &Creep) -> bool {
+		let Some(target) = self.target.resolve() else {
+			return false;
+		};
+
+		let free = reservations::free_capacity(creep, &StructureObject::from(target), self.resource);
+		if free == 0 {
+			reservations::release(creep, self.target);
+			return false;
+		}
+
+		let carried = creep.store().get_used_capacity(Some(self.resource));
+		reservations::reserve(creep, self.target, self.resource, Flow::Incoming, self.amount.unwrap_or(carried).min(free));
+		true
+	}
+}
+
+impl StateTransfer {
+	fn attempt(&mut self, creep: &Creep) -> StateResult<TransferReturn, TransferError> {
 		if let Some(last_store) = self.amount_before {
 			let current = creep.store().get_used_capacity(Some(self.resource));
 			
@@ -71,6 +99,11 @@ impl State for StateTransfer {
 			let Some(target) = self.target.resolve() else {
 				return Failed(TransferError::TargetNotReal);
 			};
+			// Other Creeps may already be on their way to fill it.
+			if !self.reserve(creep) {
+				return Failed(TransferError::TargetFull);
+			}
+
 			let target = StructureObject::from(target);
 			let Some(target) = target.as_transferable() else {
 				return Failed(TransferError::InvalidTarget);
@@ -119,6 +152,8 @@ pub enum WithdrawError {
 	TargetNotReal,
 	InvalidTarget,
 	NotEnoughCapacity,
+	/// Whatever was left is already promised to other Creeps.
+	TargetEmpty,
 	Unknown,
 }
 
@@ -133,6 +168,32 @@ impl State for StateWithdraw {
 	type Return = WithdrawReturn;
 
 	fn run(&mut self, creep: &Creep, _data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		let result = self.attempt(creep);
+		if !matches!(result, Working) {
+			reservations::release(creep, self.target);
+		}
+		result
+	}
+
+	fn reserve(&mut self, creep: &Creep) -> bool {
+		let Some(target) = self.target.resolve() else {
+			return false;
+		};
+
+		let available = reservations::available(creep, &StructureObject::from(target), self.resource);
+		if available == 0 {
+			reservations::release(creep, self.target);
+			return false;
+		}
+
+		let free = creep.store().get_free_capacity(Some(self.resource)).max(0) as u32;
+		reservations::reserve(creep, self.target, self.resource, Flow::Outgoing, self.amount.unwrap_or(free).min(available));
+		true
+	}
+}
+
+impl StateWithdraw {
+	fn attempt(&mut self, creep: &Creep) -> StateResult<WithdrawReturn, WithdrawError> {
 		if let Some(amnt) = self.amount && creep.store().get_free_capacity(Some(self.resource)) < amnt as i32 {
 			return Failed(WithdrawError::NotEnoughCapacity);
 		}
@@ -140,6 +201,9 @@ impl State for StateWithdraw {
 		let Some(target) = self.target.resolve() else {
 			return Failed(WithdrawError::TargetNotReal);
 		};
+		if !self.reserve(creep) {
+			return Failed(WithdrawError::TargetEmpty);
+		}
 		let target = StructureObject::from(target);
 		let Some(target) = target.as_withdrawable() else {
 			return Failed(WithdrawError::InvalidTarget);
@@ -165,7 +229,8 @@ impl State for StateWithdraw {
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct StateMove {
 	destination: Position,
-	path_cache: String,
+	/// The path is shared between every Creep using the same handle.
+	path: PathHandle,
 	/// Zero means exact, 1 means 1 tile off, etc.  
 	/// To interact with something at `target` range should be 1.
 	range: u8,
@@ -183,35 +248,75 @@ pub struct StateMove {
 	/// Creeps will attempt to 'move closer' until hitting their `recalc_rate` from when they get close enough.
 	lazy: bool,
 	reached_destination: bool,
+	/// Where the Creep was when it last tried to move.
+	#[serde(default)]
+	last_pos: Option<Position>,
+	/// How many ticks in a row the Creep tried to move but stayed put.  
+	/// Being tired doesn't count towards this.
+	#[serde(default)]
+	stuck_ticks: u8,
 }
 
+/// After being stuck for this many ticks, the path is recalculated around other Creeps.
+const STUCK_REPATH_TICKS: u8 = 3;
+/// After being stuck for this many ticks, the move fails.
+const STUCK_FAIL_TICKS: u8 = 12;
+
 impl StateMove {
 	pub fn new_from_ends_close(start: impl HasPosition, end: impl HasPosition) -> Self {
 		Self::new_from_ends(start, end, 1)
 	}
 
 	pub fn new_from_ends(start: impl HasPosition, end: impl HasPosition, range: u8) -> Self {
-		let start: Position = start.pos();
+		Self::new_with_laziness(start, end, range, false)
+	}
+
+	pub fn new_from_ends_lazy(start: impl HasPosition, end: impl HasPosition, range: u8) -> Self {
+		Self::new_with_laziness(start, end, range, true)
+	}
+
+	fn new_with_laziness(start: impl HasPosition, end: impl HasPosition, range: u8, lazy: bool) -> Self {
 		let end: Position = end.pos();
-		let path = find_path(&start, &end);
+		let path = PathHandle::new(start, end, Self::path_range(range, lazy));
+		// Warm the cache so the first tick of movement doesn't pay for the search.
+		ign!(path_cache::get(&path));
 
 		Self {
-			path_cache: path,
+			path,
 			destination: end,
 			range,
 			recalc_rate: 4,
 			recalc_accumulator: 0,
 			recalc_per_tile: false,
-			lazy: false,
+			lazy,
 			reached_destination: false,
+			last_pos: None,
+			stuck_ticks: 0,
 		}
 	}
 
-	pub fn new_from_ends_lazy(start: impl HasPosition, end: impl HasPosition, range: u8) -> Self {
-		Self {
-			lazy: true,
-			..Self::new_from_ends(start, end, range)
-		}
+	/// Lazy Creeps path to exactly their range, others path as close as they're
+	/// allowed to so they have somewhere to 'move closer' to.
+	fn path_range(range: u8, lazy: bool) -> u8 {
+		if lazy { range } else { range.min(1) }
+	}
+
+	/// Switches to a fresh path from wherever the Creep is.  
+	/// If the Creep is still on the shared path it started with, that path is kept so
+	/// Creeps walking the same route keep sharing it. The cache takes care of
+	/// replacing it once it's stale.
+	fn recalculate(&mut self, creep: &Creep) {
+		let shared = PathHandle { avoid_creeps: false, shared: true, ..self.path };
+		self.path = if path_cache::get(&shared).next_direction(creep.pos()).is_some() {
+			shared
+		} else {
+			PathHandle::new(creep, self.destination, Self::path_range(self.range, self.lazy))
+		};
+	}
+
+	/// Recalculates the path treating every Creep as an obstacle.
+	fn recalculate_around_creeps(&mut self, creep: &Creep) {
+		self.path = PathHandle::new(creep, self.destination, Self::path_range(self.range, self.lazy)).avoiding_creeps();
 	}
 }
 
@@ -222,6 +327,8 @@ pub enum MoveError {
 	/// This means the Creep is not at the correct position on their path.  
 	/// This usually happens because the Creep collided with something and was unable to move.
 	OffPath,
+	/// The Creep hasn't managed to move for a long time, even after pathing around other Creeps.
+	Stuck,
 }
 
 #[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
@@ -249,7 +356,23 @@ impl State for StateMove {
 			}
 		}
 
-		if self.recalc_rate != 0 {
+		if let Some(last_pos) = self.last_pos.take() {
+			if last_pos == creep.pos() {
+				self.stuck_ticks = self.stuck_ticks.saturating_add(1);
+			} else {
+				self.stuck_ticks = 0;
+			}
+		}
+
+		if self.stuck_ticks >= STUCK_FAIL_TICKS {
+			return Failed(MoveError::Stuck);
+		}
+
+		if self.stuck_ticks > 0 && self.stuck_ticks % STUCK_REPATH_TICKS == 0 {
+			log::debug!("Creep {} is stuck, pathing around other Creeps", creep.name());
+			self.recalculate_around_creeps(creep);
+			self.recalc_accumulator = 0;
+		} else if self.recalc_rate != 0 {
 			self.recalc_accumulator += 1;
 
 			if self.recalc_accumulator >= self.recalc_rate {
@@ -258,26 +381,39 @@ impl State for StateMove {
 					return Finished(Arrived);
 				}
 
-				self.path_cache = find_path(creep, &self.destination);
+				self.recalculate(creep);
 				self.recalc_accumulator = 0;
 			}
 		}
 
-		if let Err(e) = creep.move_by_path(&JsValue::from_str(&self.path_cache)) {
-			match e {
-				ErrorCode::Tired | ErrorCode::Busy => {},
-				ErrorCode::NoBodypart => return Failed(MoveError::NoMovePart),
-				ErrorCode::NotOwner => return Failed(MoveError::UnownedCreep),
-				ErrorCode::NotFound => {
-					self.path_cache = find_path(creep, &self.destination);
-					match creep.move_by_path(&JsValue::from_str(&self.path_cache)) {
-						Ok(_) | Err(ErrorCode::Tired) | Err(ErrorCode::Busy) => {},
-						_ => return Failed(MoveError::OffPath),
-					}
-				},
-				_ => unreachable!("Move should never return another error code."),
+		let path = path_cache::get(&self.path);
+		let direction = match path.next_direction(creep.pos()) {
+			Some(direction) => direction,
+			// Nowhere closer to go.
+			None if self.reached_destination && path.end() == Some(creep.pos()) => return Finished(Arrived),
+			None => {
+				self.path = PathHandle::new(creep, self.destination, Self::path_range(self.range, self.lazy)).exact();
+				let Some(direction) = path_cache::get(&self.path).next_direction(creep.pos()) else {
+					return Failed(MoveError::OffPath);
+				};
+				direction
 			}
-		} else if self.recalc_rate > 0 && self.recalc_per_tile {
+		};
+
+		if !creep.my() {
+			return Failed(MoveError::UnownedCreep);
+		}
+		if creep.get_active_bodyparts(Part::Move) == 0 {
+			return Failed(MoveError::NoMovePart);
+		}
+		if creep.spawning() || creep.fatigue() > 0 {
+			return Working;
+		}
+
+		// The actual move is issued once traffic has been resolved at the end of the tick.
+		traffic::request_move(creep, direction);
+		self.last_pos = Some(creep.pos());
+		if self.recalc_rate > 0 && self.recalc_per_tile {
 			self.recalc_accumulator += 1;
 		}
 
@@ -285,13 +421,6 @@ impl State for StateMove {
 	}
 }
 
-fn find_path(start: &impl HasPosition, end: &impl HasPosition) -> String {
-	match start.pos().find_path_to::<_, _, screeps::pathfinder::SingleRoomCostResult>(&end.pos(), Some(screeps::FindPathOptions::default().serialize(true))) {
-		screeps::Path::Serialized(path) => path,
-		_ => unreachable!()
-	}
-}
-
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct StateSinging<L> {
 	lyrics: L,
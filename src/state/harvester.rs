@@ -3,15 +3,19 @@ use crate::dynamic_stuff::DynState;
 use super::{*, general_states::*};
 use move_to::{StateMoveTo, StateMoveToExt};
 use screeps::{Creep, ErrorCode, ObjectId, ResourceType, Source };
+use crate::{logistics::{self, Claim}, reservations::Flow};
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct StateHarvesting {
 	source: ObjectId<Source>,
+	/// The tile reserved to harvest from, if one was free.
+	#[serde(default)]
+	spot: Option<Position>,
 }
 
 impl StateHarvesting {
-	pub fn new(source: ObjectId<Source>) -> Self {
-		Self { source }
+	pub fn new(source: ObjectId<Source>, spot: Option<Position>) -> Self {
+		Self { source, spot }
 	}
 }
 
@@ -34,6 +38,10 @@ impl State for StateHarvesting {
 			return Failed(GenericStateError::TargetNotReal);
 		};
 
+		if let Some(spot) = self.spot && !crate::spots::hold(creep, spot) {
+			return Failed(GenericStateError::SpotTaken);
+		}
+
 		if let Err(e) = creep.harvest(&source) {
 			match e {
 				ErrorCode::NotInRange => return Failed(GenericStateError::OutOfRange),
@@ -43,8 +51,18 @@ impl State for StateHarvesting {
 			}
 		}
 
+		match self.spot {
+			Some(spot) => crate::traffic::request_stay(creep, spot, 0),
+			None => crate::traffic::request_stay(creep, source.pos(), 1),
+		}
+
 		Working
 	}
+
+	/// Running right away when the spot was taken lets the job pick another.
+	fn reserve(&mut self, creep: &Creep) -> bool {
+		self.spot.is_none_or(|spot| crate::spots::hold(creep, spot))
+	}
 }
 
 
@@ -54,14 +72,19 @@ pub struct StateHarvesterJob {
 	current_state: PotentialState,
 	target: StructureId,
 	source: ObjectId<Source>,
+	/// Space claimed in the logistics network for the energy being delivered.
+	#[serde(default)]
+	dropoff: Option<Claim>,
 }
 
 impl StateHarvesterJob {
 	pub fn new(creep: &Creep, target: StructureId, source: ObjectId<Source>) -> Self {
 		let moving_to_source = creep.store().get_free_capacity(Some(ResourceType::Energy)) > creep.store().get_capacity(Some(ResourceType::Energy)) as i32 / 2;
+		let dropoff = if moving_to_source { None } else { logistics::energy_sink(creep) };
+		let target = dropoff.map_or(target, |claim| claim.target);
 		let dest = if moving_to_source { source.resolve().unwrap().pos() } else { target.resolve().unwrap().pos() };
 		let current_state = if moving_to_source {
-			PotentialState::Harvesting(StateHarvesting::new(source).move_to_ends(creep, dest, 1))
+			PotentialState::Harvesting(Self::move_to_source(creep, &source.resolve().unwrap()))
 		} else {
 			PotentialState::Transferring(StateTransfer::new(target, ResourceType::Energy, None).move_to_ends(creep, dest, 1))
 		};
@@ -70,8 +93,64 @@ impl StateHarvesterJob {
 			current_state,
 			target,
 			source,
+			dropoff,
 		}
 	}
+
+	/// Moves to a tile reserved next to the source, or just next to it if none are free.
+	fn move_to_source(creep: &Creep, source: &Source) -> StateMoveTo<StateHarvesting> {
+		let spot = crate::spots::claim_near_source(creep, source);
+		let state = StateHarvesting::new(source.id(), spot);
+		match spot {
+			Some(spot) => state.move_to_ends(creep, spot, 0),
+			None => state.move_to_ends(creep, source.pos(), 1),
+		}
+	}
+
+	/// Swaps the claimed dropoff for `claim`, giving up the old one.
+	fn set_dropoff(&mut self, creep: &Creep, claim: Option<Claim>) {
+		if let Some(old) = self.dropoff.take() {
+			old.release(creep);
+		}
+		self.dropoff = claim;
+	}
+}
+
+impl StateHarvesterJob {
+	/// Takes the energy the target had no room for to another request in the logistics network,
+	/// or to the controller if nothing else needs it.
+	fn deliver_elsewhere(&mut self, creep: &Creep, data: &mut CreepData, amnt: u32) -> StateResult<TransferReturn, StateHarvesterJobError> {
+		let claim = logistics::claim_request(creep, ResourceType::Energy, amnt);
+		if let Some(claim) = claim && claim.target != self.target && let Some(target) = claim.target.resolve() {
+			self.current_state = PotentialState::Transferring(StateTransfer::new(claim.target, ResourceType::Energy, None).move_to_ends(creep, target, 1));
+			self.set_dropoff(creep, Some(claim));
+			return Working;
+		}
+		if let Some(claim) = claim {
+			claim.release(creep);
+		}
+		self.set_dropoff(creep, None);
+
+		let Some(controller) = (try {
+			creep.room()?.controller()?
+		}) else {
+			return Finished(TransferReturn::Leftover(amnt));
+		};
+
+		let mut upgrade_state = super::upgrader::StateUpgraderJob::new(creep, controller.id(), self.target);
+
+		if let Failed(e) = upgrade_state.run(creep, data) {
+			log::warn!("Failed to upgrade controller while harvesting: {:?}", e);
+			return Finished(TransferReturn::Leftover(amnt));
+		}
+
+		data.current_task = Some((
+			crate::JobIdentifier { id: controller.id().into_type(), job: crate::JobFlag::Upgrade },
+			DynState::new(upgrade_state, crate::dynamic_stuff::StateFlag::UpgraderJob)
+		));
+
+		Working
+	}
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -92,16 +171,40 @@ impl State for StateHarvesterJob {
 	type Error = StateHarvesterJobError;
 	type Return = <StateTransfer as State>::Return;
 	fn run(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		if let Some(claim) = &self.dropoff {
+			claim.hold(creep, ResourceType::Energy, Flow::Incoming);
+		}
+
+		let result = self.step(creep, data);
+		if !matches!(result, Working) {
+			self.set_dropoff(creep, None);
+		}
+		result
+	}
+}
+
+impl StateHarvesterJob {
+	fn step(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<TransferReturn, StateHarvesterJobError> {
 		match self.current_state {
 			PotentialState::Harvesting(ref mut state) => {
 				match state.run(creep, data) {
 					Working => Working,
 					Finished(_) => {
-						let Some(target) = self.target.resolve() else {
+						crate::spots::release(creep);
+						let claim = logistics::energy_sink(creep);
+						self.set_dropoff(creep, claim);
+						let Some(target) = claim.map_or(self.target, |claim| claim.target).resolve() else {
 							return Failed(StateHarvesterJobError::TargetNotReal);
 						};
 						self.current_state = PotentialState::Transferring(StateTransfer::new(target.id(), ResourceType::Energy, None).move_to_ends(creep.pos(), target.pos(), 1));
-						self.run(creep, data)
+						self.step(creep, data)
+					}
+					Failed(move_to::MoveToError::StateError(GenericStateError::SpotTaken)) => {
+						let Some(source) = self.source.resolve() else {
+							return Failed(StateHarvesterJobError::SourceNotReal);
+						};
+						self.current_state = PotentialState::Harvesting(Self::move_to_source(creep, &source));
+						Working
 					}
 					Failed(e) => Failed(StateHarvesterJobError::HarvestingError(e)),
 				}
@@ -109,55 +212,16 @@ impl State for StateHarvesterJob {
 			PotentialState::Transferring(ref mut state) => {
 				match state.run(creep, data) {
 					Working => Working,
-					Finished(TransferReturn::Leftover(amnt)) => {
-						// Hacky
-						if amnt < 80 {
-							return Finished(TransferReturn::Leftover(amnt));
-						}
-
-						let Some(controller) = (try {
-							creep.room()?.controller()?
-						}) else {
-							return Finished(TransferReturn::Leftover(amnt));
-						};
-
-						let mut upgrade_state = super::upgrader::StateUpgraderJob::new(creep, controller.id(), self.target);
-
-						if let Failed(e) = upgrade_state.run(creep, data) {
-							log::warn!("Failed to upgrade controller while harvesting: {:?}", e);
-							return Finished(TransferReturn::Leftover(amnt));
-						}
-
-						data.current_task = Some((
-							crate::JobIdentifier { id: controller.id().into_type(), job: crate::JobFlag::Upgrade },
-							DynState::new(upgrade_state, crate::dynamic_stuff::StateFlag::UpgraderJob)
-						));
-
-						Working
-					},
+					// Small leftovers aren't worth another trip.
+					Finished(TransferReturn::Leftover(amnt)) if amnt < 80 => Finished(TransferReturn::Leftover(amnt)),
+					Finished(TransferReturn::Leftover(amnt)) => self.deliver_elsewhere(creep, data, amnt),
 					Finished(f) => Finished(f),
+					// The ledger only reports the target as full once other Creeps have it covered,
+					// so the energy is better spent somewhere else.
 					Failed(move_to::MoveToError::<TransferError>::StateError(TransferError::TargetFull)) => {
-						// Hacky
-						let Some(controller) = (try {
-							creep.room()?.controller()?
-						}) else {
-							return Finished(TransferReturn::Leftover(0));
-						};
-
-						let mut upgrade_state = super::upgrader::StateUpgraderJob::new(creep, controller.id(), self.target);
-
-						if let Failed(e) = upgrade_state.run(creep, data) {
-							log::warn!("Failed to upgrade controller while harvesting: {:?}", e);
-							return Finished(TransferReturn::Leftover(0));
-						}
-
-						data.current_task = Some((
-							crate::JobIdentifier { id: controller.id().into_type(), job: crate::JobFlag::Upgrade },
-							DynState::new(upgrade_state, crate::dynamic_stuff::StateFlag::UpgraderJob)
-						));
-
-						Working
-					},
+						let carried = creep.store().get_used_capacity(Some(ResourceType::Energy));
+						self.deliver_elsewhere(creep, data, carried)
+					}
 					Failed(e) => Failed(StateHarvesterJobError::TransferringError(e)),
 				}
 			}
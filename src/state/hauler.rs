@@ -0,0 +1,117 @@
+use super::{*, general_states::*};
+use move_to::{MoveToError, StateMoveTo, StateMoveToExt};
+use screeps::ResourceType;
+use crate::{logistics::{self, Claim}, reservations::Flow};
+
+/// Carries energy from an offer to a request in the logistics network.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct StateHaulerJob {
+	current_state: PotentialState,
+	dropoff: Claim,
+}
+
+impl StateHaulerJob {
+	/// Claims a trip starting at `offer`, or just a delivery if the Creep is already carrying enough.
+	/// Returns None if nothing in the network needs what the Creep could bring.
+	pub fn new(creep: &Creep, offer: StructureId) -> Option<Self> {
+		let carried = creep.store().get_used_capacity(Some(ResourceType::Energy));
+		let capacity = creep.store().get_capacity(Some(ResourceType::Energy));
+
+		if carried >= capacity / 2 {
+			let dropoff = logistics::claim_request(creep, ResourceType::Energy, carried)?;
+			return Some(Self {
+				current_state: PotentialState::Delivering(Self::deliver(creep, &dropoff)?),
+				dropoff,
+			});
+		}
+
+		let free = creep.store().get_free_capacity(Some(ResourceType::Energy)).max(0) as u32;
+		let (pickup, dropoff) = logistics::claim_pair(creep, ResourceType::Energy, free, Some(offer))?;
+		let dest = pickup.target.resolve()?;
+
+		Some(Self {
+			current_state: PotentialState::Collecting(StateWithdraw::new(pickup.target, ResourceType::Energy, None)
+				.move_to_ends(creep, dest, 1)),
+			dropoff,
+		})
+	}
+
+	fn deliver(creep: &Creep, dropoff: &Claim) -> Option<StateMoveTo<StateTransfer>> {
+		let dest = dropoff.target.resolve()?;
+		Some(StateTransfer::new(dropoff.target, ResourceType::Energy, None).move_to_ends(creep, dest, 1))
+	}
+
+	/// Finds somewhere else for the rest of the energy to go.
+	fn redirect(&mut self, creep: &Creep) -> bool {
+		let carried = creep.store().get_used_capacity(Some(ResourceType::Energy));
+		let next = logistics::claim_request(creep, ResourceType::Energy, carried)
+			.and_then(|claim| Some((Self::deliver(creep, &claim)?, claim)));
+
+		if let Some((state, claim)) = next {
+			(self.current_state, self.dropoff) = (PotentialState::Delivering(state), claim);
+			true
+		} else {
+			false
+		}
+	}
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+enum PotentialState {
+	Collecting(StateMoveTo<StateWithdraw>),
+	Delivering(StateMoveTo<StateTransfer>),
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub enum StateHaulerJobError {
+	CollectingError(<StateMoveTo<StateWithdraw> as State>::Error),
+	DeliveringError(<StateMoveTo<StateTransfer> as State>::Error),
+	TargetNotReal,
+}
+
+impl State for StateHaulerJob {
+	type Error = StateHaulerJobError;
+	type Return = TransferReturn;
+
+	fn run(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		// Withdrawing and transferring hold on to their own ends, but the delivery
+		// has to be kept while the energy is still being collected.
+		if let PotentialState::Collecting(_) = self.current_state {
+			self.dropoff.hold(creep, ResourceType::Energy, Flow::Incoming);
+		}
+
+		match self.current_state {
+			PotentialState::Collecting(ref mut state) => {
+				match state.run(creep, data) {
+					Working => Working,
+					Finished(_) => {
+						let Some(state) = Self::deliver(creep, &self.dropoff) else {
+							return Failed(StateHaulerJobError::TargetNotReal);
+						};
+						self.current_state = PotentialState::Delivering(state);
+						Working
+					}
+					Failed(e) => {
+						self.dropoff.release(creep);
+						Failed(StateHaulerJobError::CollectingError(e))
+					}
+				}
+			}
+			PotentialState::Delivering(ref mut state) => {
+				match state.run(creep, data) {
+					Working => Working,
+					Finished(TransferReturn::Leftover(amnt)) => {
+						if self.redirect(creep) {
+							Working
+						} else {
+							Finished(TransferReturn::Leftover(amnt))
+						}
+					}
+					Finished(r) => Finished(r),
+					Failed(MoveToError::StateError(TransferError::TargetFull)) if self.redirect(creep) => Working,
+					Failed(e) => Failed(StateHaulerJobError::DeliveringError(e)),
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,87 @@
+use super::{*, general_states::StateMove};
+use screeps::{ErrorCode, ObjectId, ResourceType, Source};
+
+/// Sits on a source's mining spot and harvests for the rest of the Creep's life.
+/// Energy falls into the container underneath, or is passed to the source's link.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct StateMinerJob {
+	source: ObjectId<Source>,
+	spot: Position,
+	current_state: PotentialState,
+}
+
+impl StateMinerJob {
+	pub fn new(creep: &Creep, source: ObjectId<Source>, spot: Position) -> Self {
+		Self {
+			source,
+			spot,
+			current_state: PotentialState::Moving(StateMove::new_from_ends(creep, spot, 0)),
+		}
+	}
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+enum PotentialState {
+	Moving(StateMove),
+	Mining,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub enum StateMinerJobError {
+	MovingError(<StateMove as State>::Error),
+	SourceNotReal,
+	NoBodyPart,
+	Unknown,
+}
+
+impl State for StateMinerJob {
+	type Error = StateMinerJobError;
+	type Return = !;
+
+	fn run(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		// The spot is the only one the miner can work from, so it's taken from anyone else holding it.
+		crate::spots::take(creep, self.spot);
+
+		match self.current_state {
+			PotentialState::Moving(ref mut state) => {
+				match state.run(creep, data) {
+					Working => Working,
+					Finished(_) => {
+						self.current_state = PotentialState::Mining;
+						self.run(creep, data)
+					}
+					Failed(e) => Failed(StateMinerJobError::MovingError(e)),
+				}
+			}
+			PotentialState::Mining => {
+				let Some(source) = self.source.resolve() else {
+					return Failed(StateMinerJobError::SourceNotReal);
+				};
+
+				if creep.pos() != self.spot {
+					self.current_state = PotentialState::Moving(StateMove::new_from_ends(creep, self.spot, 0));
+					return self.run(creep, data);
+				}
+
+				match creep.harvest(&source) {
+					// Waiting for the source to regenerate.
+					Ok(()) | Err(ErrorCode::NotEnough) => {}
+					Err(ErrorCode::NoBodypart) => return Failed(StateMinerJobError::NoBodyPart),
+					Err(_) => return Failed(StateMinerJobError::Unknown),
+				}
+
+				if creep.store().get_free_capacity(Some(ResourceType::Energy)) == 0 && let Some(link) = crate::mining::link_at(self.spot) {
+					match creep.transfer(&link, ResourceType::Energy, None) {
+						// The link fills faster than it's emptied at times, the energy just drops until it's sent.
+						Ok(()) | Err(ErrorCode::Full) => {}
+						Err(e) => log::warn!("Miner {} couldn't transfer to link {}: {e:?}", creep.name(), link.id()),
+					}
+				}
+
+				crate::traffic::request_stay(creep, self.spot, 0);
+
+				Working
+			}
+		}
+	}
+}
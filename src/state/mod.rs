@@ -2,6 +2,8 @@ pub mod general_states;
 pub mod harvester;
 pub mod upgrader;
 pub mod builder;
+pub mod hauler;
+pub mod miner;
 pub mod move_to;
 pub mod reoccurring;
 pub mod seppuku;
@@ -103,6 +105,13 @@ pub trait State: Clone + serde::Serialize {
 	type Error: Copy;
 	type Return: Copy;
 	fn run(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<Self::Return, Self::Error>;
+
+	/// Called every tick the state is waiting to run, such as while its Creep moves into range,
+	/// so it can keep hold of anything it reserved.
+	/// Returns false if it already knows it won't succeed.
+	fn reserve(&mut self, _creep: &Creep) -> bool {
+		true
+	}
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, Default)]
@@ -115,7 +124,7 @@ impl State for StateIdle {
 	fn run(&mut self, creep: &Creep, _data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
 		// if self.0 <= game::time() {
 			ign!(creep.say("Idling...", true));
-			ign!(creep.move_direction(screeps::Direction::Top.multi_rot(fastrand::i8(..))));
+			crate::traffic::request_move(creep, screeps::Direction::Top.multi_rot(fastrand::i8(..)));
 
 			// self.0 = game::time() + fastrand::u32(5..20);
 		// }
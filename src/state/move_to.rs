@@ -45,6 +45,11 @@ impl<S: State> State for StateMoveTo<S> {
 	type Return = S::Return;
 
 	fn run(&mut self, creep: &Creep, _data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		// Running the state right away lets it fail without walking all the way there.
+		if self.in_move_state && !self.state.reserve(creep) {
+			self.in_move_state = false;
+		}
+
 		if self.in_move_state {
 			match self.move_state.run(creep, _data) {
 				Working => return Working,
@@ -1,13 +1,21 @@
 use super::{*, general_states::*};
 use screeps::{ErrorCode, ResourceType};
+use crate::{logistics::{self, Claim}, reservations::Flow};
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct StateUpgraderJob {
 	current_state: PotentialState,
 	target: ControllerId,
+	/// Where energy is taken from. Picked from the logistics network each time the Creep runs out.
 	container: StructureId,
 	recurring: bool,
 	moving_to_container: bool,
+	/// The tile reserved to upgrade from, if one was free.
+	#[serde(default)]
+	spot: Option<Position>,
+	/// Energy claimed in the logistics network for the trip to the container.
+	#[serde(default)]
+	pickup: Option<Claim>,
 }
 
 impl StateUpgraderJob {
@@ -20,15 +28,41 @@ impl StateUpgraderJob {
 
 	pub fn new_recurring(creep: &Creep, target: ControllerId, container: StructureId) -> Self {
 		let moving_to_container = creep.store().get_used_capacity(Some(ResourceType::Energy)) < creep.store().get_capacity(Some(ResourceType::Energy)) / 2;
-		let dest = if moving_to_container { container.resolve().unwrap().pos() } else { target.resolve().unwrap().pos() };
-
-		Self {
-			current_state: PotentialState::Moving(StateMove::new_from_ends(creep.pos(), dest, 1)),
+		let pickup = if moving_to_container { logistics::energy_source(creep) } else { None };
+		let container = pickup.map_or(container, |claim| claim.target);
+		let mut state = Self {
+			current_state: PotentialState::Upgrading,
 			target,
 			container,
 			recurring: true,
 			moving_to_container,
+			spot: None,
+			pickup,
+		};
+
+		state.current_state = if moving_to_container {
+			PotentialState::Moving(StateMove::new_from_ends(creep.pos(), container.resolve().unwrap().pos(), 1))
+		} else {
+			state.move_to_controller(creep, &target.resolve().unwrap())
+		};
+		state
+	}
+
+	/// Moves to a tile reserved in range of the controller, or just into range if none are free.
+	fn move_to_controller(&mut self, creep: &Creep, controller: &StructureController) -> PotentialState {
+		self.spot = crate::spots::claim_near_controller(creep, controller);
+		PotentialState::Moving(match self.spot {
+			Some(spot) => StateMove::new_from_ends(creep.pos(), spot, 0),
+			None => StateMove::new_from_ends(creep.pos(), controller.pos(), 3),
+		})
+	}
+
+	/// Swaps the claimed pickup for `claim`, giving up the old one.
+	fn set_pickup(&mut self, creep: &Creep, claim: Option<Claim>) {
+		if let Some(old) = self.pickup.take() {
+			old.release(creep);
 		}
+		self.pickup = claim;
 	}
 }
 
@@ -53,6 +87,28 @@ impl State for StateUpgraderJob {
 	type Error = StateUpgraderJobError;
 	type Return = ();
 	fn run(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<Self::Return, Self::Error> {
+		if let Some(claim) = &self.pickup {
+			claim.hold(creep, ResourceType::Energy, Flow::Outgoing);
+		}
+
+		let result = self.step(creep, data);
+		if !matches!(result, Working) {
+			self.set_pickup(creep, None);
+		}
+		result
+	}
+}
+
+impl StateUpgraderJob {
+	fn step(&mut self, creep: &Creep, data: &mut CreepData) -> StateResult<(), StateUpgraderJobError> {
+		// Someone else has the tile, such as after a reset, so find another.
+		if let Some(spot) = self.spot && !crate::spots::hold(creep, spot) {
+			let Some(target) = self.target.resolve() else {
+				return Failed(StateUpgraderJobError::TargetNotReal);
+			};
+			self.current_state = self.move_to_controller(creep, &target);
+		}
+
 		match self.current_state {
 			PotentialState::Withdrawing(ref mut state) => {
 				match state.run(creep, data) {
@@ -61,7 +117,9 @@ impl State for StateUpgraderJob {
 						let Some(target) = self.target.resolve() else {
 							return Failed(StateUpgraderJobError::TargetNotReal);
 						};
-						(self.moving_to_container, self.current_state) = (false, PotentialState::Moving(StateMove::new_from_ends(creep.pos(), target.pos(), 3)));
+						self.moving_to_container = false;
+						self.set_pickup(creep, None);
+						self.current_state = self.move_to_controller(creep, &target);
 						Working
 					}
 					Failed(e) => Failed(StateUpgraderJobError::WithdrawingError(e)),
@@ -72,22 +130,32 @@ impl State for StateUpgraderJob {
 					return Failed(StateUpgraderJobError::TargetNotReal);
 				};
 				match creep.upgrade_controller(&target) {
-					Ok(_) => Working,
+					Ok(_) => {
+						match self.spot {
+							Some(spot) => crate::traffic::request_stay(creep, spot, 0),
+							None => crate::traffic::request_stay(creep, target.pos(), 3),
+						}
+						Working
+					}
 					Err(e) => {
 						match e {
 							ErrorCode::NotInRange => {
 								// Switch back to moving.
-								let dest = target.pos();
-								self.current_state = PotentialState::Moving(StateMove::new_from_ends_close(creep.pos(), dest));
-								self.run(creep, data)
+								self.current_state = self.move_to_controller(creep, &target);
+								self.step(creep, data)
 							}
 							ErrorCode::NotEnough => {
 								if self.recurring {
+									crate::spots::release(creep);
+									self.spot = None;
+									let claim = logistics::energy_source(creep);
+									self.set_pickup(creep, claim);
+									self.container = claim.map_or(self.container, |claim| claim.target);
 									let Some(dest) = self.container.resolve() else {
 										return Failed(StateUpgraderJobError::TargetNotReal);
 									};
 									(self.moving_to_container, self.current_state) = (true, PotentialState::Moving(StateMove::new_from_ends_close(creep.pos(), dest.pos())));
-									self.run(creep, data)
+									self.step(creep, data)
 								} else {
 									Finished(())
 								}
@@ -110,7 +178,7 @@ impl State for StateUpgraderJob {
 						}
 
 						// We run again because a completed move state means we've already arrived.
-						self.run(creep, data)
+						self.step(creep, data)
 					}
 					Failed(e) => Failed(StateUpgraderJobError::MovingError(e)),
 				}
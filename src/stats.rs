@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+use screeps::{find, ResourceType};
+
+use crate::{segments, utils::prelude::*};
+
+/// A snapshot of the colony, written as JSON for graphs outside the game.
+#[derive(serde::Serialize)]
+struct Stats {
+	tick: u32,
+	/// CPU used this tick, up to when the stats were taken.
+	cpu: f64,
+	bucket: i32,
+	gcl: Progress,
+	creeps: usize,
+	rooms: BTreeMap<String, RoomStats>,
+}
+
+#[derive(serde::Serialize)]
+struct Progress {
+	level: u32,
+	progress: f64,
+	progress_total: f64,
+}
+
+#[derive(serde::Serialize)]
+struct RoomStats {
+	controller: Progress,
+	energy_available: u32,
+	energy_capacity: u32,
+	/// Energy in the room's storage, if it has one.
+	stored: Option<u32>,
+	creeps: usize,
+	construction_sites: usize,
+}
+
+/// Call once at the end of the tick. Replaces the stats segment with how things stand now.
+pub fn record() {
+	let rooms = game::rooms().values()
+		.filter_map(|room| {
+			let controller = room.controller().filter(|c| c.my())?;
+			Some((room.name().to_string(), RoomStats {
+				controller: Progress {
+					level: controller.level() as u32,
+					progress: controller.progress().unwrap_or(0) as f64,
+					progress_total: controller.progress_total().unwrap_or(0) as f64,
+				},
+				energy_available: room.energy_available(),
+				energy_capacity: room.energy_capacity_available(),
+				stored: room.storage().map(|s| s.store().get_used_capacity(Some(ResourceType::Energy))),
+				creeps: room.find(find::MY_CREEPS, None).len(),
+				construction_sites: room.find(find::MY_CONSTRUCTION_SITES, None).len(),
+			}))
+		})
+		.collect();
+
+	let stats = Stats {
+		tick: game::time(),
+		cpu: game::cpu::get_used(),
+		bucket: game::cpu::bucket(),
+		gcl: Progress { level: game::gcl::level(), progress: game::gcl::progress(), progress_total: game::gcl::progress_total() },
+		creeps: game::creeps().keys().count(),
+		rooms,
+	};
+
+	let result = serde_json::to_string(&stats).map_err(|e| e.to_string())
+		.and_then(|json| segments::write(segments::STATS, &json));
+	if let Err(err) = result {
+		log::warn!("Failed to save stats: '{err}'");
+	}
+}
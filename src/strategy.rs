@@ -0,0 +1,165 @@
+use screeps::{find, Part};
+
+use crate::{utils::prelude::*, JobFlag};
+
+use Part::*;
+
+/// Bodies can't have more parts than this.
+const MAX_BODY_PARTS: usize = 50;
+
+/// How a room is run at its current stage of development.
+/// Decides which jobs are generated, how many Creeps the room wants and what they look like.
+pub trait RoomStrategy {
+	fn name(&self) -> &'static str;
+
+	/// Whether jobs of this kind should be generated for the room.
+	fn wants_jobs(&self, job: JobFlag) -> bool;
+
+	/// The most upgrader jobs the room should have, given how many harvester jobs it has.
+	fn max_upgraders(&self, harvester_jobs: u32) -> u32 {
+		harvester_jobs.div_ceil(2).min(20)
+	}
+
+	/// The most Creeps the room wants, from the baseline the job generators came up with.
+	/// Nothing is spawned past it, even if the economy could afford more.
+	fn population_target(&self, baseline: usize) -> usize {
+		baseline
+	}
+
+	/// The parts repeated to make up a body, as many times as the room can afford.
+	fn body_template(&self) -> &'static [Part];
+
+	/// Whether to spawn as soon as the template is affordable once, rather than waiting
+	/// for the room's extensions to fill up.
+	fn spawn_eagerly(&self) -> bool {
+		false
+	}
+
+	/// The body to spawn next, or None if the room can't afford one yet.
+	fn spawn_body(&self, room: &Room) -> Option<Vec<Part>> {
+		let budget = if self.spawn_eagerly() { room.energy_available() } else { room.energy_capacity_available() };
+		let body = scaled_body(self.body_template(), budget);
+		let cost: u32 = body.iter().map(|p| p.cost()).sum();
+		(!body.is_empty() && cost <= room.energy_available()).then_some(body)
+	}
+}
+
+/// Repeats `template` as many times as `energy` allows, up to the part limit.
+pub fn scaled_body(template: &[Part], energy: u32) -> Vec<Part> {
+	let cost: u32 = template.iter().map(|p| p.cost()).sum();
+	if cost == 0 || template.is_empty() {
+		return Vec::new();
+	}
+
+	let repeats = (energy / cost) as usize;
+	let repeats = repeats.min(MAX_BODY_PARTS / template.len());
+	let mut body: Vec<Part> = template.iter().cycle().take(template.len() * repeats).copied().collect();
+	// Tougher parts first and movement last, so damage hits the least important parts first.
+	body.sort_by_key(|p| match p {
+		Tough => 0,
+		Move => 2,
+		_ => 1,
+	});
+	body
+}
+
+/// A fresh room, or one that lost its Creeps. Small Creeps spawned as soon as
+/// possible, and nothing but keeping the spawn fed and the controller from downgrading.
+pub struct Bootstrap;
+
+impl RoomStrategy for Bootstrap {
+	fn name(&self) -> &'static str { "Bootstrap" }
+
+	fn wants_jobs(&self, job: JobFlag) -> bool {
+		matches!(job, JobFlag::Harvest | JobFlag::Upgrade)
+	}
+
+	fn max_upgraders(&self, _harvester_jobs: u32) -> u32 {
+		1
+	}
+
+	fn body_template(&self) -> &'static [Part] {
+		&[Work, Carry, Move]
+	}
+
+	fn spawn_eagerly(&self) -> bool {
+		true
+	}
+}
+
+/// Extensions are coming up but there's no storage. Everything runs through the spawn.
+pub struct EarlyEconomy;
+
+impl RoomStrategy for EarlyEconomy {
+	fn name(&self) -> &'static str { "EarlyEconomy" }
+
+	fn wants_jobs(&self, _job: JobFlag) -> bool {
+		true
+	}
+
+	fn body_template(&self) -> &'static [Part] {
+		&[Work, Carry, Carry, Move, Move]
+	}
+}
+
+/// The room has a storage to buffer energy, so fewer, larger Creeps do the work.
+pub struct StorageEconomy;
+
+impl RoomStrategy for StorageEconomy {
+	fn name(&self) -> &'static str { "StorageEconomy" }
+
+	fn wants_jobs(&self, _job: JobFlag) -> bool {
+		true
+	}
+
+	fn population_target(&self, baseline: usize) -> usize {
+		(baseline * 2).div_ceil(3)
+	}
+
+	fn body_template(&self) -> &'static [Part] {
+		&[Work, Work, Carry, Carry, Move, Move]
+	}
+}
+
+/// A fully upgraded room. Upgrading is capped by the game, so most of the energy
+/// goes elsewhere.
+pub struct MatureRoom;
+
+impl RoomStrategy for MatureRoom {
+	fn name(&self) -> &'static str { "MatureRoom" }
+
+	fn wants_jobs(&self, _job: JobFlag) -> bool {
+		true
+	}
+
+	fn max_upgraders(&self, _harvester_jobs: u32) -> u32 {
+		// Controllers at level 8 only take 15 energy a tick.
+		1
+	}
+
+	fn population_target(&self, baseline: usize) -> usize {
+		baseline.div_ceil(2)
+	}
+
+	fn body_template(&self) -> &'static [Part] {
+		&[Work, Work, Carry, Carry, Move, Move]
+	}
+}
+
+/// Picks the strategy for a room from its controller level and what's been built.  
+/// Rooms that lost most of their Creeps drop back to bootstrapping.
+pub fn select(room: &Room) -> &'static dyn RoomStrategy {
+	let level = room.controller().map(|c| c.level()).unwrap_or(0);
+	let creeps = room.find(find::MY_CREEPS, None).len();
+	let has_storage = room.storage().is_some();
+
+	if level <= 1 || creeps < 3 {
+		&Bootstrap
+	} else if level >= 8 {
+		&MatureRoom
+	} else if has_storage {
+		&StorageEconomy
+	} else {
+		&EarlyEconomy
+	}
+}
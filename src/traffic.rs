@@ -0,0 +1,164 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}};
+
+use screeps::{Direction, Position, Terrain};
+
+use crate::{cost_matrix, utils::prelude::*};
+
+thread_local! {
+	static INTENTS: RefCell<Intents> = RefCell::new(Intents::default());
+}
+
+#[derive(Default)]
+struct Intents {
+	/// Creeps that want to move this tick, by name, with the tile they want to move to.
+	moves: HashMap<String, (Creep, Position)>,
+	/// Creeps that are working in place, by name.
+	/// They may be moved aside, but only to tiles still within `range` of `anchor`.
+	stationary: HashMap<String, (Position, u8)>,
+}
+
+/// Registers the Creep's wish to step in `direction` this tick.
+/// The move intent is only issued when [`resolve`] is called at the end of the tick.
+pub fn request_move(creep: &Creep, direction: Direction) {
+	let Ok(target) = creep.pos().checked_add_direction(direction) else {
+		return;
+	};
+
+	INTENTS.with_borrow_mut(|intents| {
+		intents.moves.insert(creep.name(), (creep.clone(), target));
+	});
+}
+
+/// Marks the Creep as working in place within `range` of `anchor`.
+/// Stationary Creeps are only shoved to tiles where they can keep working.
+pub fn request_stay(creep: &Creep, anchor: Position, range: u8) {
+	INTENTS.with_borrow_mut(|intents| {
+		intents.stationary.insert(creep.name(), (anchor, range));
+	});
+}
+
+/// Resolves conflicts between every registered move and issues the move intents.
+/// Creeps that want each other's tiles are swapped, and Creeps in the way that
+/// aren't moving are shoved aside if there's somewhere for them to go.
+pub fn resolve() {
+	let Intents { mut moves, stationary } = INTENTS.take();
+
+	let creeps: HashMap<String, Creep> = game::creeps().entries().filter(|(_, c)| !c.spawning()).collect();
+	let occupants: HashMap<Position, String> = creeps.iter().map(|(name, creep)| (creep.pos(), name.clone())).collect();
+
+	// Tiles that some Creep has already been granted this tick.
+	let mut claimed: HashSet<Position> = HashSet::new();
+	// Creeps whose move was settled while resolving someone else's.
+	let mut settled: HashSet<String> = HashSet::new();
+	// Movers that only get their tile if the Creep currently on it actually leaves, by the name of that Creep.
+	let mut needs: HashMap<String, String> = HashMap::new();
+
+	let mut names: Vec<String> = moves.keys().cloned().collect();
+	// Movers are handled in a stable order so results don't flicker between ticks.
+	names.sort_unstable();
+
+	for name in names {
+		if settled.contains(&name) {
+			continue;
+		}
+		let Some((creep, target)) = moves.get(&name).cloned() else { continue };
+		let from = creep.pos();
+
+		if claimed.contains(&target) {
+			moves.remove(&name);
+			continue;
+		}
+
+		let Some(occupant_name) = occupants.get(&target).filter(|o| **o != name) else {
+			claimed.insert(target);
+			continue;
+		};
+
+		// Tired Creeps can't leave this tick, whatever they'd like to do.
+		if creeps[occupant_name].fatigue() > 0 {
+			moves.remove(&name);
+			continue;
+		}
+
+		match moves.get(occupant_name) {
+			// Wants our tile, so we swap.
+			Some((_, occupant_target)) if *occupant_target == from => {
+				claimed.insert(target);
+				claimed.insert(from);
+				settled.insert(occupant_name.clone());
+				needs.insert(name.clone(), occupant_name.clone());
+			}
+			// Moving elsewhere, the engine lets us follow into the tile it leaves.
+			Some(_) => {
+				claimed.insert(target);
+				needs.insert(name.clone(), occupant_name.clone());
+			}
+			// Not moving, so it has to be shoved.
+			None => {
+				let occupant = &creeps[occupant_name];
+				let anchor = stationary.get(occupant_name).copied();
+				match shove_target(occupant, from, anchor, &occupants, &claimed) {
+					Some(shove) => {
+						claimed.insert(target);
+						claimed.insert(shove);
+						moves.insert(occupant_name.clone(), (occupant.clone(), shove));
+						settled.insert(occupant_name.clone());
+						needs.insert(name.clone(), occupant_name.clone());
+					}
+					None => {
+						// Nowhere to go, the mover waits and will repath if it's stuck for too long.
+						moves.remove(&name);
+					}
+				}
+			}
+		}
+	}
+
+	let mut issued = HashMap::new();
+	let mut names: Vec<String> = moves.keys().cloned().collect();
+	names.sort_unstable();
+	for name in names {
+		issue(&name, &moves, &needs, &mut issued);
+	}
+}
+
+/// Issues the Creep's move once the Creep it's moving into, if any, has had its own move accepted.
+/// A mover whose occupant couldn't move is held back instead of walking into it.
+/// Returns whether the move was issued.
+fn issue(name: &str, moves: &HashMap<String, (Creep, Position)>, needs: &HashMap<String, String>, issued: &mut HashMap<String, bool>) -> bool {
+	if let Some(&done) = issued.get(name) {
+		return done;
+	}
+	// Counted as issued while in progress, so swaps and rotations don't wait on themselves.
+	issued.insert(name.to_string(), true);
+
+	let Some((creep, target)) = moves.get(name) else {
+		issued.insert(name.to_string(), false);
+		return false;
+	};
+	let clear = needs.get(name).is_none_or(|occupant| issue(occupant, moves, needs, issued));
+	let done = clear && creep.pos().get_direction_to(*target).is_some_and(|direction| creep.move_direction(direction).is_ok());
+
+	issued.insert(name.to_string(), done);
+	done
+}
+
+/// Picks a tile to shove `creep` to so `mover` can take its place.
+/// Free tiles are preferred, and swapping into the mover's tile is the last resort.
+fn shove_target(creep: &Creep, mover: Position, anchor: Option<(Position, u8)>, occupants: &HashMap<Position, String>, claimed: &HashSet<Position>) -> Option<Position> {
+	let pos = creep.pos();
+	let in_anchor_range = |p: Position| anchor.is_none_or(|(anchor, range)| p.in_range_to(anchor, range as u32));
+
+	let terrain = creep.room().map(|room| room.get_terrain());
+	let costs = cost_matrix::get(pos.room_name(), &[]);
+
+	let free = Direction::iter()
+		.filter_map(|dir| pos.checked_add_direction(*dir).ok())
+		.filter(|p| p.room_name() == pos.room_name() && *p != mover)
+		.filter(|p| terrain.as_ref().is_none_or(|t| t.get_xy(p.xy()) != Terrain::Wall))
+		.filter(|p| costs.as_ref().is_none_or(|c| c.get(p.xy()) != u8::MAX))
+		.filter(|p| !occupants.contains_key(p) && !claimed.contains(p))
+		.find(|p| in_anchor_range(*p));
+
+	free.or_else(|| (in_anchor_range(mover) && !claimed.contains(&mover)).then_some(mover))
+}